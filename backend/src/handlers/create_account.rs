@@ -23,10 +23,19 @@ pub fn create_account(
     host: ft_sdk::Host,
     app_url: ft_sdk::AppUrl,
     ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+    client: common::session::ClientInfo,
 ) -> ft_sdk::form::Result {
-    let account_meta = validate(payload, &mut conn, &code)?;
+    let account_meta = validate(payload, &mut conn, &code, &config)?;
     ft_sdk::println!("Account meta done for {}", account_meta.name);
 
+    email_auth::utils::rate_limit_mail(
+        &mut conn,
+        "create-account",
+        &account_meta.email,
+        &client,
+        "email",
+    )?;
+
     let uid = match account_meta.user_id.clone() {
         Some(uid) => {
             ft_sdk::auth::provider::update_user(
@@ -45,16 +54,16 @@ pub fn create_account(
         )?,
     };
 
-    let ft_sdk::SessionID(sid) =
-        ft_sdk::auth::provider::login(&mut conn, &uid, sid.map(ft_sdk::SessionID))?;
+    let sid = common::session::reusable(&mut conn, sid, &config.session)?;
+    let ft_sdk::SessionID(sid) = ft_sdk::auth::provider::login(&mut conn, &uid, sid)?;
+    common::session::on_login(&mut conn, &sid, &client, &config.session)?;
 
     ft_sdk::println!("Create User done for sid {sid}");
 
     let next = next.unwrap_or_else(|| "/".to_string());
     if account_meta.pre_verified {
-        return Ok(
-            ft_sdk::form::redirect(next)?.with_cookie(common::session_cookie(sid.as_str(), host)?)
-        );
+        let cookie = common::session_cookie(sid.as_str(), host, &config.session)?;
+        return Ok(ft_sdk::form::redirect(next)?.with_cookie(cookie));
     }
 
     let conf_link = confirmation_link(
@@ -65,7 +74,8 @@ pub fn create_account(
     );
     ft_sdk::println!("Confirmation link added {conf_link}");
     send_confirmation_email(account_meta.email, account_meta.name, &conf_link, &config)?;
-    Ok(ft_sdk::form::redirect(next)?.with_cookie(common::session_cookie(sid.as_str(), host)?))
+    let cookie = common::session_cookie(sid.as_str(), host, &config.session)?;
+    Ok(ft_sdk::form::redirect(next)?.with_cookie(cookie))
 }
 
 struct CreateAccount {
@@ -124,10 +134,11 @@ fn validate(
     payload: CreateAccountPayload,
     conn: &mut ft_sdk::Connection,
     code: &Option<String>,
+    config: &crate::Config,
 ) -> Result<CreateAccount, ft_sdk::Error> {
     let mut errors = std::collections::HashMap::new();
 
-    payload.validate(conn, &mut errors)?;
+    payload.validate(conn, &mut errors, config)?;
 
     if !errors.is_empty() {
         return Err(ft_sdk::SpecialError::Multi(errors).into());
@@ -206,7 +217,7 @@ fn validate(
     Ok(CreateAccount {
         pre_verified,
         user_id,
-        hashed_password: hashed_password(&payload.password),
+        hashed_password: hashed_password(&payload.password, config)?,
         email: payload.email,
         name: payload.name,
         #[cfg(feature = "username")]
@@ -232,6 +243,7 @@ impl CreateAccountPayload {
         &self,
         conn: &mut ft_sdk::Connection,
         errors: &mut std::collections::HashMap<String, String>,
+        config: &crate::Config,
     ) -> Result<(), ft_sdk::Error> {
         if !validator::ValidateEmail::validate_email(&self.email) {
             errors.insert("email".to_string(), "Invalid email format.".to_string());
@@ -244,7 +256,9 @@ impl CreateAccountPayload {
             );
         }
 
-        if let Some(message) = is_strong_password(&self.password) {
+        if let Some(message) =
+            email_auth::password_policy::check(&self.password, &config.password_policy)
+        {
             errors.insert("password".to_string(), message);
         }
 
@@ -271,21 +285,45 @@ impl CreateAccountPayload {
     }
 }
 
-pub(crate) fn hashed_password(password: &str) -> String {
+/// Hash `password` with the parameters from `config`, see `password_hash::Config`
+pub(crate) fn hashed_password(
+    password: &str,
+    config: &crate::Config,
+) -> Result<String, ft_sdk::Error> {
     let salt = argon2::password_hash::SaltString::generate(&mut ft_sdk::Rng {});
-    let argon2 = argon2::Argon2::default();
+    let argon2 = config.password_hash.argon2()?;
     argon2::password_hash::PasswordHasher::hash_password(&argon2, password.as_bytes(), &salt)
-        .unwrap()
-        .to_string()
+        .map(|hash| hash.to_string())
+        .map_err(|e| ft_sdk::server_error!("error hashing password: {:?}", e).into())
 }
 
-pub(crate) fn is_strong_password(password: &str) -> Option<String> {
-    // TODO: better password validation
-    if password.len() < 4 {
-        return Some("password is too short".to_string());
+/// Check `password` against a hash generated by `hashed_password`. The parameters are read from
+/// the hash, so hashes made with older parameters still verify. Hashes imported from other
+/// systems are checked by `password_hash::verify_legacy`.
+pub(crate) fn verify_hashed_password(hash: &str, password: &str) -> Result<bool, ft_sdk::Error> {
+    if !hash.starts_with("$argon2") {
+        return email_auth::password_hash::verify_legacy(hash, password);
     }
 
-    None
+    let parsed_hash = match argon2::PasswordHash::new(hash) {
+        Ok(v) => v,
+        Err(e) => {
+            ft_sdk::println!("error parsing hash: {:?}", e);
+            return Err(ft_sdk::server_error!("error verifying password: {:?}", e).into());
+        }
+    };
+
+    let password_match = argon2::PasswordVerifier::verify_password(
+        &argon2::Argon2::default(),
+        password.as_bytes(),
+        &parsed_hash,
+    );
+
+    match password_match {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(ft_sdk::server_error!("error verifying password: {:?}", e).into()),
+    }
 }
 
 fn validate_verified_email(
@@ -4,9 +4,18 @@ pub fn forgot_password(
     ft_sdk::Required(username_or_email): ft_sdk::Required<"username-or-email">,
     ft_sdk::Optional(next): ft_sdk::Optional<"next">,
     app_url: ft_sdk::AppUrl,
+    client: common::session::ClientInfo,
     ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
 ) -> ft_sdk::form::Result {
     let (user_id, email, data) = get_user_data(&mut conn, username_or_email)?;
+
+    email_auth::utils::rate_limit_mail(
+        &mut conn,
+        "forgot-password",
+        &email,
+        &client,
+        "username-or-email",
+    )?;
     let name = data.name.clone().unwrap_or_else(|| email.clone());
 
     let set_password_url = app_url.join("/set-password/").inspect_err(|e| {
@@ -0,0 +1,423 @@
+/// Session data key for the `OAuthState` of an ongoing GitHub login
+const OAUTH_STATE_KEY: &str = "github-oauth";
+/// Custom data key that stores the GitHub user id, as a string
+const GITHUB_ID_KEY: &str = "github_id";
+
+/// GitHub login settings, part of the lets-auth config (see `config.ftd`). The endpoints default
+/// to github.com, they are configurable so a mock server can stand in for GitHub in tests. The
+/// client secret is read from the `GITHUB_CLIENT_SECRET` env variable.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// GitHub login is disabled if this is not set
+    #[serde(default)]
+    pub github_client_id: Option<String>,
+    #[serde(default = "default_authorize_url")]
+    pub github_authorize_url: String,
+    #[serde(default = "default_token_url")]
+    pub github_token_url: String,
+    #[serde(default = "default_api_url")]
+    pub github_api_url: String,
+}
+
+fn default_authorize_url() -> String {
+    "https://github.com/login/oauth/authorize".to_string()
+}
+
+fn default_token_url() -> String {
+    "https://github.com/login/oauth/access_token".to_string()
+}
+
+fn default_api_url() -> String {
+    "https://api.github.com".to_string()
+}
+
+/// Stored in the session between `github_login` and `github_callback`
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct OAuthState {
+    state: String,
+    next: String,
+}
+
+/// Start GitHub login, this is available on /backend/github-login/ route
+///
+/// The user is sent to GitHub to authorize the app, GitHub sends them back to `github_callback`.
+/// A random `state` is kept in the session and checked in the callback, so the callback can not
+/// be triggered by another site.
+#[ft_sdk::processor]
+pub fn github_login(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    host: ft_sdk::Host,
+    app_url: ft_sdk::AppUrl,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::processor::Result {
+    let client_id = config
+        .github
+        .github_client_id
+        .clone()
+        .ok_or_else(|| ft_sdk::server_error!("GitHub login is not configured"))?;
+
+    // the state has to outlive this request, so an anonymous session is created if needed
+    let sid = match common::session::reusable(&mut conn, sid, &config.session)? {
+        Some(ft_sdk::SessionID(sid)) => sid,
+        None => common::session::create_anonymous(&mut conn, &config.session)?,
+    };
+
+    let state = ft_sdk::Rng::generate_key(32);
+
+    common::session::set_data(
+        &mut conn,
+        &sid,
+        OAUTH_STATE_KEY,
+        &OAuthState {
+            state: state.clone(),
+            next: next.unwrap_or_else(|| "/".to_string()),
+        },
+    )?;
+
+    let redirect_uri = callback_url(&app_url)?;
+
+    let url = format!(
+        "{authorize_url}?client_id={client_id}&redirect_uri={redirect_uri}&scope={scope}&state={state}",
+        authorize_url = config.github.github_authorize_url,
+        redirect_uri = common::fetch::url_encode(&redirect_uri),
+        scope = common::fetch::url_encode("read:user user:email"),
+    );
+
+    let cookie = common::session_cookie(sid.as_str(), host, &config.session)?;
+    Ok(ft_sdk::processor::temporary_redirect(url)?.with_cookie(cookie))
+}
+
+/// GitHub sends the user back here after they authorize the app, this is available on
+/// /backend/github-callback/ route
+///
+/// The `code` is exchanged for an access token, which is used to fetch the profile and the
+/// verified emails of the user. The user is found by their GitHub id. If this is their first
+/// login, the GitHub account is linked to the logged in user, or a user is created under the
+/// `github` provider (see `common::link::resolve`). Users with two-factor authentication still
+/// have to enter their code, see `common::login::complete_login`.
+#[ft_sdk::processor]
+#[expect(clippy::too_many_arguments)]
+pub fn github_callback(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Query(code): ft_sdk::Query<"code", Option<String>>,
+    ft_sdk::Query(state): ft_sdk::Query<"state", Option<String>>,
+    ft_sdk::Query(error): ft_sdk::Query<"error", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    host: ft_sdk::Host,
+    client: common::session::ClientInfo,
+    app_url: ft_sdk::AppUrl,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::processor::Result {
+    let sid = sid.ok_or_else(|| ft_sdk::single_error("state", "GitHub login was not started."))?;
+
+    let saved: OAuthState = common::session::get_data(&mut conn, &sid, OAUTH_STATE_KEY)?
+        .ok_or_else(|| ft_sdk::single_error("state", "GitHub login was not started."))?;
+
+    // the state is single use
+    common::session::remove_data(&mut conn, &sid, OAUTH_STATE_KEY)?;
+
+    if state.as_deref() != Some(saved.state.as_str()) {
+        ft_sdk::println!("github state mismatch");
+        return Err(ft_sdk::single_error("state", "Invalid state, please try again.").into());
+    }
+
+    if let Some(error) = error {
+        ft_sdk::println!("github returned error: {error}");
+        return Err(ft_sdk::single_error("code", "GitHub login was cancelled.").into());
+    }
+
+    let code = code.ok_or_else(|| ft_sdk::single_error("code", "Missing code."))?;
+
+    let client_secret = ft_sdk::env::var("GITHUB_CLIENT_SECRET".to_string())
+        .ok_or_else(|| ft_sdk::server_error!("GITHUB_CLIENT_SECRET is not set"))?;
+
+    let (user, emails) = fetch_user(
+        &config.github,
+        &client_secret,
+        &code,
+        &callback_url(&app_url)?,
+        &|request| ft_sdk::http::send(request).map_err(|e| format!("{e:?}")),
+    )?;
+
+    let data = user.to_provider_data(&emails);
+
+    let existing = match ft_sdk::auth::provider::user_data_by_custom_attribute(
+        &mut conn,
+        email_auth::GITHUB_PROVIDER_ID,
+        GITHUB_ID_KEY,
+        &user.id.to_string(),
+    ) {
+        Ok((user_id, _)) => Some(user_id),
+        Err(ft_sdk::auth::UserDataError::NoDataFound) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    // a logged in user going through this flow links their GitHub account
+    let logged_in = common::session::ud(&mut conn, Some(sid.clone()), &config.session)?
+        .map(|ud| ft_sdk::UserId(ud.id));
+
+    let user_id = common::link::resolve(
+        &mut conn,
+        email_auth::GITHUB_PROVIDER_ID,
+        data,
+        existing,
+        logged_in,
+        &config.link,
+    )?;
+
+    // two-factor authentication set up with the email provider applies to GitHub logins too
+    let two_factor = common::login::user_two_factor_enabled(&mut conn, &user_id)?;
+
+    let (next, cookie) = common::login::complete_login(
+        &mut conn,
+        &user_id,
+        two_factor,
+        Some(sid),
+        saved.next,
+        host,
+        &client,
+        &app_url,
+        &config.session,
+    )?;
+
+    Ok(ft_sdk::processor::temporary_redirect(next)?.with_cookie(cookie))
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct GithubUser {
+    id: i64,
+    login: String,
+    name: Option<String>,
+    avatar_url: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct GithubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+impl GithubUser {
+    fn to_provider_data(&self, emails: &[GithubEmail]) -> ft_sdk::auth::ProviderData {
+        // primary email first, so it is the one `first_email` returns
+        let mut emails: Vec<&GithubEmail> = emails.iter().collect();
+        emails.sort_by_key(|e| !e.primary);
+
+        ft_sdk::auth::ProviderData {
+            // the login can be renamed and then taken by someone else, the id never changes
+            identity: format!("github:{}", self.id),
+            username: Some(self.login.clone()),
+            name: self.name.clone(),
+            emails: emails.iter().map(|e| e.email.clone()).collect(),
+            verified_emails: emails
+                .iter()
+                .filter(|e| e.verified)
+                .map(|e| e.email.clone())
+                .collect(),
+            profile_picture: self.avatar_url.clone(),
+            custom: serde_json::json!({
+                GITHUB_ID_KEY: self.id.to_string(),
+            }),
+        }
+    }
+}
+
+/// GitHub checks this against the callback url set in the OAuth app settings
+fn callback_url(app_url: &ft_sdk::AppUrl) -> Result<String, ft_sdk::Error> {
+    Ok(app_url.join("/backend/github-callback/").inspect_err(|e| {
+        ft_sdk::println!("auth.wasm: failed to join url: {:?}", e);
+    })?)
+}
+
+/// Sends a request to GitHub, `ft_sdk::http::send` outside of tests
+type Sender<'a> =
+    &'a dyn Fn(http::Request<bytes::Bytes>) -> Result<http::Response<bytes::Bytes>, String>;
+
+/// Exchange the `code` GitHub sent to the callback for an access token and fetch the profile and
+/// the emails of the user it belongs to
+fn fetch_user(
+    config: &Config,
+    client_secret: &str,
+    code: &str,
+    redirect_uri: &str,
+    send: Sender,
+) -> Result<(GithubUser, Vec<GithubEmail>), ft_sdk::Error> {
+    let access_token = exchange_code(config, client_secret, code, redirect_uri, send)?;
+    let user = api_get(config, &access_token, "/user", send)?;
+    let emails = api_get(config, &access_token, "/user/emails", send)?;
+
+    Ok((user, emails))
+}
+
+/// Exchange the `code` GitHub sent to the callback for an access token
+fn exchange_code(
+    config: &Config,
+    client_secret: &str,
+    code: &str,
+    redirect_uri: &str,
+    send: Sender,
+) -> Result<String, ft_sdk::Error> {
+    #[derive(serde::Deserialize)]
+    struct TokenResponse {
+        access_token: Option<String>,
+        error: Option<String>,
+        error_description: Option<String>,
+    }
+
+    let client_id = config
+        .github_client_id
+        .as_ref()
+        .ok_or_else(|| ft_sdk::server_error!("GitHub login is not configured"))?;
+
+    let body = serde_json::json!({
+        "client_id": client_id,
+        "client_secret": client_secret,
+        "code": code,
+        "redirect_uri": redirect_uri,
+    });
+
+    let request = http::Request::builder()
+        .method(http::Method::POST)
+        .uri(&config.github_token_url)
+        .header(http::header::ACCEPT, "application/json")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(bytes::Bytes::from(serde_json::to_vec(&body)?))?;
+
+    let response: TokenResponse = common::fetch::json_with(request, send)?;
+
+    match response.access_token {
+        Some(token) => Ok(token),
+        None => {
+            ft_sdk::println!(
+                "github token exchange failed: {:?}: {:?}",
+                response.error,
+                response.error_description
+            );
+            Err(ft_sdk::single_error("code", "GitHub login failed, please try again.").into())
+        }
+    }
+}
+
+/// GET `path` of the GitHub API on behalf of the user
+fn api_get<T: serde::de::DeserializeOwned>(
+    config: &Config,
+    access_token: &str,
+    path: &str,
+    send: Sender,
+) -> Result<T, ft_sdk::Error> {
+    let request = http::Request::builder()
+        .method(http::Method::GET)
+        .uri(format!(
+            "{}{path}",
+            config.github_api_url.trim_end_matches('/')
+        ))
+        .header(http::header::ACCEPT, "application/vnd.github+json")
+        .header(
+            http::header::AUTHORIZATION,
+            format!("Bearer {access_token}"),
+        )
+        // GitHub rejects requests without a user agent
+        .header(http::header::USER_AGENT, "lets-auth")
+        .body(bytes::Bytes::new())?;
+
+    common::fetch::json_with(request, send)
+}
+
+#[cfg(test)]
+mod tests {
+    fn config() -> super::Config {
+        super::Config {
+            github_client_id: Some("client-id".to_string()),
+            github_authorize_url: super::default_authorize_url(),
+            github_token_url: "https://github.test/login/oauth/access_token".to_string(),
+            github_api_url: "https://api.github.test/".to_string(),
+        }
+    }
+
+    fn respond(status: u16, body: serde_json::Value) -> http::Response<bytes::Bytes> {
+        http::Response::builder()
+            .status(status)
+            .body(bytes::Bytes::from(body.to_string()))
+            .unwrap()
+    }
+
+    /// Stands in for the token and the user endpoints of GitHub
+    fn github(
+        request: http::Request<bytes::Bytes>,
+    ) -> Result<http::Response<bytes::Bytes>, String> {
+        let token = "Bearer token-1";
+
+        Ok(
+            match (
+                request.method().as_str(),
+                request.uri().to_string().as_str(),
+            ) {
+                ("POST", "https://github.test/login/oauth/access_token") => {
+                    let body: serde_json::Value = serde_json::from_slice(request.body()).unwrap();
+                    assert_eq!(body["client_id"], "client-id");
+                    assert_eq!(body["client_secret"], "secret");
+                    assert_eq!(
+                        body["redirect_uri"],
+                        "https://example.com/-/auth/backend/github-callback/"
+                    );
+
+                    if body["code"] == "good-code" {
+                        respond(200, serde_json::json!({"access_token": "token-1"}))
+                    } else {
+                        // GitHub reports a bad code with a 200 and an error in the body
+                        respond(200, serde_json::json!({"error": "bad_verification_code"}))
+                    }
+                }
+                (_, _) if request.headers()[http::header::AUTHORIZATION] != token => {
+                    respond(401, serde_json::json!({"message": "Bad credentials"}))
+                }
+                ("GET", "https://api.github.test/user") => respond(
+                    200,
+                    serde_json::json!({
+                        "id": 583231,
+                        "login": "octocat",
+                        "name": "The Octocat",
+                        "avatar_url": "https://avatars.github.test/u/583231",
+                    }),
+                ),
+                ("GET", "https://api.github.test/user/emails") => respond(
+                    200,
+                    serde_json::json!([
+                        {"email": "old@example.com", "primary": false, "verified": false},
+                        {"email": "octocat@example.com", "primary": true, "verified": true},
+                        {"email": "work@example.com", "primary": false, "verified": true},
+                    ]),
+                ),
+                _ => respond(404, serde_json::json!({"message": "Not Found"})),
+            },
+        )
+    }
+
+    #[test]
+    fn test_fetch_user() {
+        let redirect_uri = "https://example.com/-/auth/backend/github-callback/";
+
+        let (user, emails) =
+            super::fetch_user(&config(), "secret", "good-code", redirect_uri, &github).unwrap();
+        let data = user.to_provider_data(&emails);
+
+        assert_eq!(user.id, 583231);
+        assert_eq!(data.identity, "github:583231");
+        assert_eq!(data.username.as_deref(), Some("octocat"));
+        assert_eq!(
+            data.emails,
+            vec!["octocat@example.com", "old@example.com", "work@example.com"]
+        );
+        assert_eq!(
+            data.verified_emails,
+            vec!["octocat@example.com", "work@example.com"]
+        );
+        assert_eq!(data.custom[super::GITHUB_ID_KEY], "583231");
+
+        assert!(super::fetch_user(&config(), "secret", "bad-code", redirect_uri, &github).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+/// Key in `fastn_session.data` that holds the `ImportReport` of the last import
+const IMPORT_REPORT_KEY: &str = "import-users-report";
+/// `import_users_report` shows the report for these many minutes
+const IMPORT_REPORT_EXPIRE_MINUTES: i64 = 30;
+
+/// Import email subscribers, this is available on /import-users/ route. Only the super user
+/// (`super-user-id` in the config) can use it.
+///
+/// `content` is CSV with an `email` and an optional `name` column, or JSON lines with the same
+/// keys. Every new email gets a placeholder user in the shape `create_account` expects: no
+/// identity, only `data -> 'email' -> 'emails'`, and a confirmation code in
+/// `data -> 'subscription' -> 'confirmation-code'`. The code goes in links sent to the subscriber,
+/// `create_account` treats the email of someone signing up with it as verified.
+///
+/// Emails that belong to an existing user, as an email or a verified email of any provider, are
+/// skipped. A row that fails to be stored is reported as failed, the rows after it are still
+/// imported. The report has one entry per row, in input order. It is kept in the session for
+/// `IMPORT_REPORT_EXPIRE_MINUTES` so `import_users_report` can show it, and the user is sent to
+/// `next`.
+#[ft_sdk::form]
+pub fn import_users(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Form(payload): ft_sdk::Form<ImportUsersPayload>,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let (user_id, sid) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+
+    if config.super_user_id != Some(user_id.0) {
+        return Err(ft_sdk::unauthorised!("Only the super user can import users.").into());
+    }
+
+    let rows = match payload.format {
+        ImportFormat::Csv => parse_csv(&payload.content),
+        ImportFormat::Jsonl => parse_jsonl(&payload.content),
+    };
+
+    let mut seen = std::collections::HashSet::new();
+    let mut report = Vec::with_capacity(rows.len());
+
+    for (line, row) in rows {
+        let result = match row {
+            Ok(row) => {
+                let email = row.email.trim().to_string();
+                import_row(&mut conn, row, &mut seen).unwrap_or_else(|e| {
+                    ft_sdk::println!("failed to import line {line}: {e:?}");
+                    // a later row with the same email gets another try
+                    seen.remove(&email.to_lowercase());
+                    RowResult::Failed {
+                        email,
+                        message: "Could not be imported, please try again.".to_string(),
+                    }
+                })
+            }
+            Err(message) => RowResult::Invalid {
+                email: None,
+                message,
+            },
+        };
+
+        report.push(RowReport { line, result });
+    }
+
+    common::session::set_data(
+        &mut conn,
+        &sid,
+        IMPORT_REPORT_KEY,
+        &ImportReport {
+            rows: report,
+            created_at: ft_sdk::env::now()
+                .timestamp_nanos_opt()
+                .expect("unexpected out of range datetime"),
+        },
+    )?;
+
+    ft_sdk::form::redirect(next.unwrap_or_else(|| "/".to_string()))
+}
+
+/// The report of the last `import_users` of this session, this is available on
+/// /import-users-report/ route. Empty once `IMPORT_REPORT_EXPIRE_MINUTES` have passed.
+#[ft_sdk::data]
+pub fn import_users_report(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::data::Result {
+    let (user_id, sid) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+
+    if config.super_user_id != Some(user_id.0) {
+        return Err(ft_sdk::unauthorised!("Only the super user can import users.").into());
+    }
+
+    let report: Option<ImportReport> =
+        common::session::get_data(&mut conn, &sid, IMPORT_REPORT_KEY)?;
+
+    let rows = match report {
+        Some(report)
+            if chrono::DateTime::from_timestamp_nanos(report.created_at)
+                + chrono::TimeDelta::minutes(IMPORT_REPORT_EXPIRE_MINUTES)
+                > ft_sdk::env::now() =>
+        {
+            report.rows
+        }
+        _ => vec![],
+    };
+
+    ft_sdk::data::json(rows)
+}
+
+/// Stored in the session by `import_users`, read by `import_users_report`
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct ImportReport {
+    rows: Vec<RowReport>,
+    created_at: i64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ImportUsersPayload {
+    format: ImportFormat,
+    content: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum ImportFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ImportRow {
+    email: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct RowReport {
+    /// 1 based line number in `content`
+    line: usize,
+    #[serde(flatten)]
+    result: RowResult,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+enum RowResult {
+    #[serde(rename_all = "kebab-case")]
+    Created {
+        email: String,
+        user_id: i64,
+        confirmation_code: String,
+    },
+    /// The email belongs to an existing user, or appeared earlier in the same import
+    Duplicate { email: String },
+    Invalid {
+        email: Option<String>,
+        message: String,
+    },
+    /// The row is valid but storing it failed
+    Failed { email: String, message: String },
+}
+
+fn import_row(
+    conn: &mut ft_sdk::Connection,
+    row: ImportRow,
+    seen: &mut std::collections::HashSet<String>,
+) -> Result<RowResult, ft_sdk::Error> {
+    let email = row.email.trim().to_string();
+
+    if !validator::ValidateEmail::validate_email(&email) {
+        return Ok(RowResult::Invalid {
+            email: Some(email),
+            message: "Invalid email format.".to_string(),
+        });
+    }
+
+    if !seen.insert(email.to_lowercase()) || email_exists(conn, &email)? {
+        return Ok(RowResult::Duplicate { email });
+    }
+
+    let confirmation_code = email_auth::handlers::create_account::generate_key(64);
+    let name = row.name.as_deref().filter(|n| !n.is_empty());
+    let user_id = create_placeholder(conn, &email, name, &confirmation_code)?;
+
+    Ok(RowResult::Created {
+        email,
+        user_id,
+        confirmation_code,
+    })
+}
+
+/// Rows of `content` along with their line number. A header row naming the columns is required.
+fn parse_csv(content: &str) -> Vec<(usize, Result<ImportRow, String>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return vec![(1, Err(e.to_string()))],
+    };
+
+    reader
+        .records()
+        .map(|record| match record {
+            Ok(record) => (
+                record.position().map_or(0, |p| p.line() as usize),
+                record
+                    .deserialize(Some(&headers))
+                    .map_err(|e| e.to_string()),
+            ),
+            Err(e) => (
+                e.position().map_or(0, |p| p.line() as usize),
+                Err(e.to_string()),
+            ),
+        })
+        .collect()
+}
+
+/// Rows of `content` along with their line number, blank lines are skipped
+fn parse_jsonl(content: &str) -> Vec<(usize, Result<ImportRow, String>)> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, serde_json::from_str(line).map_err(|e| e.to_string())))
+        .collect()
+}
+
+/// `email` is an email or a verified email of any user, with any provider
+fn email_exists(conn: &mut ft_sdk::Connection, email: &str) -> Result<bool, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        count: i64,
+    }
+
+    let found = diesel::sql_query(
+        r#"
+        SELECT COUNT(*) AS count
+        FROM fastn_user, json_each(fastn_user.data) AS provider
+        WHERE
+            EXISTS (
+                SELECT 1
+                FROM json_each(provider.value -> 'emails')
+                WHERE lower(value) = lower($1)
+            )
+            OR EXISTS (
+                SELECT 1
+                FROM json_each(provider.value -> 'verified_emails')
+                WHERE lower(value) = lower($1)
+            )
+        "#,
+    )
+    .bind::<diesel::sql_types::Text, _>(email)
+    .get_result::<Count>(conn)?;
+
+    Ok(found.count > 0)
+}
+
+fn create_placeholder(
+    conn: &mut ft_sdk::Connection,
+    email: &str,
+    name: Option<&str>,
+    confirmation_code: &str,
+) -> Result<i64, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Id {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        id: i64,
+    }
+
+    // see `create_account` for why the email provider data holds nothing but the email
+    let mut subscription = serde_json::json!({
+        "confirmation-code": [confirmation_code],
+    });
+    if let Some(name) = name {
+        subscription["name"] = serde_json::Value::String(name.to_string());
+    }
+
+    let data = serde_json::json!({
+        email_auth::PROVIDER_ID: { "emails": [email] },
+        email_auth::SUBSCRIPTION_PROVIDER_ID: subscription,
+    });
+
+    let now = ft_sdk::env::now();
+
+    let user = diesel::sql_query(
+        r#"
+        INSERT INTO fastn_user (name, identity, data, created_at, updated_at)
+        VALUES ($1, NULL, $2, $3, $4)
+        RETURNING id
+        "#,
+    )
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(name)
+    .bind::<diesel::sql_types::Text, _>(data.to_string())
+    .bind::<diesel::sql_types::Timestamptz, _>(now)
+    .bind::<diesel::sql_types::Timestamptz, _>(now)
+    .get_result::<Id>(conn)?;
+
+    Ok(user.id)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse() {
+        let csv = super::parse_csv("email,name\na@example.com, A\n\nb@example.com\n");
+        let csv: Vec<_> = csv
+            .into_iter()
+            .map(|(_, row)| row.map(|r| (r.email, r.name)))
+            .collect();
+        assert_eq!(
+            csv,
+            vec![
+                Ok(("a@example.com".to_string(), Some("A".to_string()))),
+                Ok(("b@example.com".to_string(), None)),
+            ]
+        );
+        let lines: Vec<_> = super::parse_csv("email,name\na@example.com, A\n\nb@example.com\n")
+            .iter()
+            .map(|(line, _)| *line)
+            .collect();
+        assert_eq!(lines, vec![2, 4]);
+
+        let jsonl = super::parse_jsonl(
+            "{\"email\": \"a@example.com\"}\n\n{\"name\": \"B\"}\n{\"email\": \"c@example.com\", \"name\": \"C\"}",
+        );
+        let lines: Vec<_> = jsonl.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![1, 3, 4]);
+        assert!(jsonl[1].1.is_err());
+        assert_eq!(jsonl[2].1.as_ref().unwrap().name.as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn test_report_round_trip() {
+        let rows = vec![
+            super::RowReport {
+                line: 2,
+                result: super::RowResult::Created {
+                    email: "a@example.com".to_string(),
+                    user_id: 7,
+                    confirmation_code: "code".to_string(),
+                },
+            },
+            super::RowReport {
+                line: 3,
+                result: super::RowResult::Failed {
+                    email: "b@example.com".to_string(),
+                    message: "Could not be imported, please try again.".to_string(),
+                },
+            },
+        ];
+
+        let json = serde_json::to_value(&rows).unwrap();
+        assert_eq!(
+            json[0],
+            serde_json::json!({
+                "line": 2,
+                "status": "created",
+                "email": "a@example.com",
+                "user-id": 7,
+                "confirmation-code": "code",
+            })
+        );
+        assert_eq!(json[1]["status"], "failed");
+
+        let back: Vec<super::RowReport> = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+    }
+}
@@ -0,0 +1,74 @@
+/// List the providers the logged in user can log in with, this is available on
+/// /linked-providers/ route
+#[ft_sdk::data]
+pub fn linked_providers(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::data::Result {
+    let (user_id, _) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+
+    ft_sdk::data::json(login_providers(&mut conn, &user_id)?)
+}
+
+/// Unlink a provider from the logged in user, this is available on /unlink-provider/ route
+///
+/// The last provider the user can log in with can not be unlinked. Neither can the provider the
+/// two-factor settings are stored under while two-factor authentication is on: unlinking it would
+/// turn two-factor authentication off without the code `two_factor::disable_two_factor` asks for.
+#[ft_sdk::form]
+pub fn unlink_provider(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Form(payload): ft_sdk::Form<UnlinkProviderPayload>,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let (user_id, _) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+
+    let providers = login_providers(&mut conn, &user_id)?;
+
+    if !providers.contains(&payload.provider) {
+        return Err(ft_sdk::single_error("provider", "This provider is not linked.").into());
+    }
+
+    if providers.len() == 1 {
+        return Err(ft_sdk::single_error(
+            "provider",
+            "You can not unlink the only way you can log in.",
+        )
+        .into());
+    }
+
+    if payload.provider == common::login::TWO_FACTOR_PROVIDER_ID
+        && common::login::user_two_factor_enabled(&mut conn, &user_id)?
+    {
+        return Err(ft_sdk::single_error(
+            "provider",
+            "Turn off two-factor authentication before unlinking this provider.",
+        )
+        .into());
+    }
+
+    common::link::unlink(&mut conn, &user_id, &payload.provider)?;
+    ft_sdk::println!("Unlinked {} from user {}", payload.provider, user_id.0);
+
+    ft_sdk::form::redirect(next.unwrap_or_else(|| "/".to_string()))
+}
+
+/// Providers of `user_id` that can be used to log in, the subscription data is not one of them
+fn login_providers(
+    conn: &mut ft_sdk::Connection,
+    user_id: &ft_sdk::UserId,
+) -> Result<Vec<String>, ft_sdk::Error> {
+    Ok(common::link::providers(conn, user_id)?
+        .into_iter()
+        .filter(|p| p != email_auth::SUBSCRIPTION_PROVIDER_ID)
+        .collect())
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct UnlinkProviderPayload {
+    provider: String,
+}
@@ -0,0 +1,319 @@
+//! Brute-force protection for `login`.
+//!
+//! Failed login attempts are counted in `fastn_login_attempt`, per user for known accounts and per
+//! identity string for unknown ones, so probing for accounts is throttled as well. Locks are kept
+//! per identity and client (see `client_key`): after `LOCKOUT_THRESHOLD` failures from a client,
+//! every further failure locks that client out for twice as long as the previous one, capped at
+//! `MAX_LOCKOUT_SECONDS`. Anyone who knows an email can fail logins for it, so a lock on the
+//! identity alone would let them keep its owner out.
+//!
+//! The per identity count only decides when the owner of the account gets an email with a link
+//! to `unlock_account`, it never blocks a login.
+
+/// Failures allowed before the first lock
+pub const LOCKOUT_THRESHOLD: i64 = 5;
+/// Length of the first lock, doubles with every failure after that
+const BASE_LOCKOUT_SECONDS: i64 = 60;
+const MAX_LOCKOUT_SECONDS: i64 = 24 * 60 * 60;
+/// Failures older than this are forgotten
+const FAILURE_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+
+/// Unlock the account the `code` was sent for, this is available on /backend/unlock-account/
+/// route. The link is sent by email when the account gets locked.
+#[ft_sdk::processor]
+pub fn unlock_account(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Query(code): ft_sdk::Query<"code">,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+) -> ft_sdk::processor::Result {
+    let (user_id, mut data) = match ft_sdk::auth::provider::user_data_by_custom_attribute(
+        &mut conn,
+        email_auth::PROVIDER_ID,
+        email_auth::ACCOUNT_UNLOCK_CODE_KEY,
+        &code,
+    ) {
+        Ok(v) => v,
+        Err(ft_sdk::auth::UserDataError::NoDataFound) => {
+            return Err(ft_sdk::single_error("code", "Invalid unlock code.").into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let sent_at = data
+        .get_custom(email_auth::ACCOUNT_UNLOCK_CODE_SENT_AT)
+        .expect("account_unlock_code_sent_at should exists if the account was found");
+
+    if key_expired(chrono::DateTime::from_timestamp_nanos(sent_at)) {
+        return Err(ft_sdk::single_error("code", "Unlock code expired.").into());
+    }
+
+    let custom = data
+        .custom
+        .as_object_mut()
+        .expect("custom is a json object");
+    custom.remove(email_auth::ACCOUNT_UNLOCK_CODE_KEY);
+    custom.remove(email_auth::ACCOUNT_UNLOCK_CODE_SENT_AT);
+
+    ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    clear_all(&mut conn, &user_key(&user_id))?;
+
+    let next = next.unwrap_or_else(|| "/".to_string());
+    ft_sdk::processor::temporary_redirect(next)
+}
+
+/// Key under which failures of a known user are counted
+pub fn user_key(user_id: &ft_sdk::UserId) -> String {
+    format!("uid:{}", user_id.0)
+}
+
+/// Key under which failures for an identity that does not belong to any user are counted
+pub fn identity_key(identity: &str) -> String {
+    format!("identity:{}", identity.trim().to_lowercase())
+}
+
+/// Key under which failures for `key`, a `user_key` or an `identity_key`, made by `client` are
+/// counted and locked
+pub fn client_key(key: &str, client: &common::session::ClientInfo) -> String {
+    format!("{key}|ip:{}", client.ip.as_deref().unwrap_or("unknown"))
+}
+
+/// Key under which wrong second factor codes of a user are counted, see
+/// `two_factor::verify_two_factor`, `two_factor::disable_two_factor` and
+/// `two_factor::regenerate_recovery_codes`
+pub fn two_factor_key(user_id: &ft_sdk::UserId) -> String {
+    format!("two-factor-uid:{}", user_id.0)
+}
+
+/// Error against `field` if `key` is locked right now. Attempts made while locked are not counted,
+/// the password is not even checked.
+pub fn check(conn: &mut ft_sdk::Connection, key: &str, field: &str) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Attempt {
+        #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+        locked_until: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    let attempt =
+        match diesel::sql_query("SELECT locked_until FROM fastn_login_attempt WHERE key = $1")
+            .bind::<diesel::sql_types::Text, _>(key)
+            .get_result::<Attempt>(conn)
+        {
+            Ok(v) => v,
+            Err(diesel::result::Error::NotFound) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+    if attempt
+        .locked_until
+        .is_some_and(|until| until > ft_sdk::env::now())
+    {
+        ft_sdk::println!("login attempt for locked key: {key}");
+        return Err(ft_sdk::single_error(
+            field,
+            "Too many failed login attempts. Try again later.",
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Count a failed attempt for `key` and lock it if it has failed too often. Returns the number of
+/// recent failures, including this one.
+pub fn record_failure(conn: &mut ft_sdk::Connection, key: &str) -> Result<i64, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    let failures = count_failure(conn, key)?;
+
+    if let Some(seconds) = lockout_seconds(failures) {
+        ft_sdk::println!("locking {key} for {seconds} seconds");
+        diesel::sql_query("UPDATE fastn_login_attempt SET locked_until = $1 WHERE key = $2")
+            .bind::<diesel::sql_types::Timestamptz, _>(
+                ft_sdk::env::now() + chrono::TimeDelta::seconds(seconds),
+            )
+            .bind::<diesel::sql_types::Text, _>(key)
+            .execute(conn)?;
+    }
+
+    Ok(failures)
+}
+
+/// Count a failed attempt for `key` without ever locking it. Returns the number of recent
+/// failures, including this one.
+pub fn count_failure(conn: &mut ft_sdk::Connection, key: &str) -> Result<i64, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Attempt {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        failures: i64,
+    }
+
+    let now = ft_sdk::env::now();
+
+    let attempt = diesel::sql_query(
+        r#"
+        INSERT INTO fastn_login_attempt (key, failures, locked_until, updated_at)
+        VALUES ($1, 1, NULL, $2)
+        ON CONFLICT (key) DO UPDATE SET
+            failures = CASE WHEN updated_at < $3 THEN 1 ELSE failures + 1 END,
+            updated_at = $2
+        RETURNING failures
+        "#,
+    )
+    .bind::<diesel::sql_types::Text, _>(key)
+    .bind::<diesel::sql_types::Timestamptz, _>(now)
+    .bind::<diesel::sql_types::Timestamptz, _>(
+        now - chrono::TimeDelta::seconds(FAILURE_WINDOW_SECONDS),
+    )
+    .get_result::<Attempt>(conn)?;
+
+    Ok(attempt.failures)
+}
+
+/// Forget the failed attempts of `key`, called after a successful login or an unlock.
+pub fn clear(conn: &mut ft_sdk::Connection, key: &str) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    diesel::sql_query("DELETE FROM fastn_login_attempt WHERE key = $1")
+        .bind::<diesel::sql_types::Text, _>(key)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Forget the failed attempts of `key` and of every client of it, see `client_key`. Called when
+/// the owner unlocks their account.
+pub fn clear_all(conn: &mut ft_sdk::Connection, key: &str) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    diesel::sql_query("DELETE FROM fastn_login_attempt WHERE key = $1 OR key LIKE $1 || '|%'")
+        .bind::<diesel::sql_types::Text, _>(key)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// How long to lock after `failures` failed attempts, `None` if this many failures are allowed.
+fn lockout_seconds(failures: i64) -> Option<i64> {
+    if failures < LOCKOUT_THRESHOLD {
+        return None;
+    }
+
+    // 2^18 minutes is well over the cap, this also keeps the shift from overflowing
+    let exponent = (failures - LOCKOUT_THRESHOLD).min(18) as u32;
+    Some((BASE_LOCKOUT_SECONDS << exponent).min(MAX_LOCKOUT_SECONDS))
+}
+
+/// Generate a new unlock code for the user and email them the unlock link
+pub fn send_unlock_link(
+    conn: &mut ft_sdk::Connection,
+    user_id: &ft_sdk::UserId,
+    mut data: ft_sdk::auth::ProviderData,
+    app_url: &ft_sdk::AppUrl,
+    config: &crate::Config,
+) -> Result<(), ft_sdk::Error> {
+    let email = match data.first_email() {
+        Some(e) => e,
+        None => {
+            ft_sdk::println!("no email found for locked user, unlock link not sent");
+            return Ok(());
+        }
+    };
+
+    let key = ft_sdk::Rng::generate_key(64);
+
+    let unlock_url = app_url.join("/backend/unlock-account/").inspect_err(|e| {
+        ft_sdk::println!("auth.wasm: failed to join url: {:?}", e);
+    })?;
+    let link = format!("{unlock_url}?code={key}");
+
+    let custom = data.custom.as_object_mut().unwrap();
+    custom.insert(
+        email_auth::ACCOUNT_UNLOCK_CODE_KEY.to_string(),
+        serde_json::Value::String(key),
+    );
+    custom.insert(
+        email_auth::ACCOUNT_UNLOCK_CODE_SENT_AT.to_string(),
+        serde_json::Value::Number(
+            ft_sdk::env::now()
+                .timestamp_nanos_opt()
+                .expect("unexpected out of range datetime")
+                .into(),
+        ),
+    );
+
+    let name = data.name.clone().unwrap_or_else(|| email.clone());
+
+    ft_sdk::auth::provider::update_user(conn, email_auth::PROVIDER_ID, user_id, data, false)?;
+
+    send_account_locked_email(email, name, &link, config)
+}
+
+pub fn send_account_locked_email(
+    email: String,
+    name: String,
+    link: &str,
+    config: &crate::Config,
+) -> Result<(), ft_sdk::Error> {
+    let from = config.from_email();
+
+    ft_sdk::println!("Found email sender: {from:?},");
+
+    if let Err(e) = ft_sdk::email::send(&ft_sdk::Email {
+        from,
+        to: smallvec::smallvec![(name.clone(), email).into()],
+        reply_to: Some(smallvec::smallvec![config.reply_to()]),
+        cc: smallvec::smallvec![],
+        bcc: smallvec::smallvec![],
+        mkind: "account-locked".to_string(),
+        content: ft_sdk::EmailContent::FromMKind {
+            context: Some(
+                serde_json::json!({
+                    "link": link,
+                    "name": name,
+                })
+                .as_object()
+                .unwrap()
+                .to_owned(),
+            ),
+        },
+    }) {
+        ft_sdk::println!("auth.wasm: failed to queue email: {:?}", e);
+        return Err(e.into());
+    }
+
+    ft_sdk::println!("Email added to the queue");
+
+    Ok(())
+}
+
+/// check if it has been 1 day since the unlock code was sent. The threshold can be
+/// configured using ACCOUNT_UNLOCK_EXPIRE_HOURS env variable
+fn key_expired(sent_at: chrono::DateTime<chrono::Utc>) -> bool {
+    let expiry_limit_in_hours: i64 = ft_sdk::env::var("ACCOUNT_UNLOCK_EXPIRE_HOURS".to_string())
+        .map(|v| {
+            v.parse()
+                .expect("ACCOUNT_UNLOCK_EXPIRE_HOURS should be a number")
+        })
+        .unwrap_or(24);
+
+    sent_at + chrono::TimeDelta::hours(expiry_limit_in_hours) <= ft_sdk::env::now()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_lockout_seconds() {
+        assert_eq!(super::lockout_seconds(1), None);
+        assert_eq!(super::lockout_seconds(4), None);
+        assert_eq!(super::lockout_seconds(5), Some(60));
+        assert_eq!(super::lockout_seconds(6), Some(120));
+        assert_eq!(super::lockout_seconds(8), Some(480));
+        assert_eq!(super::lockout_seconds(16), Some(24 * 60 * 60));
+        assert_eq!(super::lockout_seconds(1000), Some(24 * 60 * 60));
+    }
+}
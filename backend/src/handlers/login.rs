@@ -1,23 +1,39 @@
 #[derive(Debug)]
 pub struct Login {
     user_id: ft_sdk::auth::UserId,
+    /// the user has two-factor authentication enabled, the password alone does not log them in
+    two_factor: bool,
 }
 
 #[ft_sdk::form]
+#[expect(clippy::too_many_arguments)]
 pub fn login(
     mut conn: ft_sdk::Connection,
     ft_sdk::Form(payload): ft_sdk::Form<LoginPayload>,
     ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
     ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
     host: ft_sdk::Host,
+    client: common::session::ClientInfo,
+    app_url: ft_sdk::AppUrl,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
 ) -> ft_sdk::form::Result {
-    let login_meta = validate(&mut conn, payload)?;
-
-    let ft_sdk::SessionID(sid) =
-        ft_sdk::auth::provider::login(&mut conn, &login_meta.user_id, sid.map(ft_sdk::SessionID))?;
+    let login_meta = validate(&mut conn, payload, &client, &app_url, &config)?;
 
     let next = next.unwrap_or_else(|| "/".to_string());
-    Ok(ft_sdk::form::redirect(next)?.with_cookie(common::session_cookie(sid.as_str(), host)?))
+
+    let (next, cookie) = common::login::complete_login(
+        &mut conn,
+        &login_meta.user_id,
+        login_meta.two_factor,
+        sid,
+        next,
+        host,
+        &client,
+        &app_url,
+        &config.session,
+    )?;
+
+    Ok(ft_sdk::form::redirect(next)?.with_cookie(cookie))
 }
 
 impl Login {
@@ -35,53 +51,109 @@ impl Login {
             }
         };
 
-        let parsed_hash = match argon2::PasswordHash::new(stored_password.as_str()) {
-            Ok(v) => v,
-            Err(e) => {
-                ft_sdk::println!("error parsing hash: {:?}", e);
-                return Err(ft_sdk::server_error!("error verifying password: {:?}", e).into());
+        email_auth::handlers::create_account::verify_hashed_password(&stored_password, password)
+    }
+
+    /// Rehash `password` if its stored hash was made with other parameters than the configured
+    /// ones, so raising the cost in the config reaches every user as they log in. Called only
+    /// after `match_password` succeeds.
+    fn rehash_if_needed(
+        conn: &mut ft_sdk::Connection,
+        user_id: &ft_sdk::UserId,
+        mut ud: ft_sdk::auth::ProviderData,
+        password: &str,
+        config: &crate::Config,
+    ) -> Result<ft_sdk::auth::ProviderData, ft_sdk::Error> {
+        match ud.get_custom::<String>("hashed_password") {
+            Some(hash) if email_auth::password_hash::needs_rehash(&hash, &config.password_hash) => {
             }
-        };
+            _ => return Ok(ud),
+        }
 
-        let password_match = argon2::PasswordVerifier::verify_password(
-            &argon2::Argon2::default(),
-            password.as_bytes(),
-            &parsed_hash,
-        );
+        ud.custom
+            .as_object_mut()
+            .expect("custom is a json object")
+            .insert(
+                "hashed_password".to_string(),
+                serde_json::Value::String(email_auth::handlers::create_account::hashed_password(
+                    password, config,
+                )?),
+            );
 
-        match password_match {
-            Ok(()) => Ok(true),
-            Err(argon2::password_hash::Error::Password) => Ok(false),
-            Err(e) => Err(ft_sdk::server_error!("error verifying password: {:?}", e).into()),
-        }
+        ft_sdk::auth::provider::update_user(
+            conn,
+            email_auth::PROVIDER_ID,
+            user_id,
+            ud.clone(),
+            false,
+        )?;
+        ft_sdk::println!("rehashed password of user {}", user_id.0);
+
+        Ok(ud)
     }
 }
 
-fn validate(conn: &mut ft_sdk::Connection, payload: LoginPayload) -> Result<Login, ft_sdk::Error> {
-    let (user_id, user_data) = match email_auth::utils::user_data_from_email_or_username(
-        conn,
-        payload.username_or_email,
-    ) {
-        Ok(v) => v,
-        Err(ft_sdk::auth::UserDataError::NoDataFound) => {
-            ft_sdk::println!("username not found");
-            return Err(
-                ft_sdk::single_error("username-or-email", "Incorrect username/password.").into(),
-            );
-        }
-        Err(e) => return Err(e.into()),
-    };
+fn validate(
+    conn: &mut ft_sdk::Connection,
+    payload: LoginPayload,
+    client: &common::session::ClientInfo,
+    app_url: &ft_sdk::AppUrl,
+    config: &crate::Config,
+) -> Result<Login, ft_sdk::Error> {
+    let username_or_email = payload.username_or_email.as_str();
+
+    let (user_id, user_data) =
+        match email_auth::utils::user_data_from_email_or_username(conn, username_or_email) {
+            Ok(v) => v,
+            Err(ft_sdk::auth::UserDataError::NoDataFound) => {
+                ft_sdk::println!("username not found");
+                // unknown identities are throttled too, so the lockout does not tell which accounts
+                // exist
+                let key = email_auth::handlers::lockout::client_key(
+                    &email_auth::handlers::lockout::identity_key(username_or_email),
+                    client,
+                );
+                email_auth::handlers::lockout::check(conn, &key, "username-or-email")?;
+                email_auth::handlers::lockout::record_failure(conn, &key)?;
+                return Err(ft_sdk::single_error(
+                    "username-or-email",
+                    "Incorrect username/password.",
+                )
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+    let key = email_auth::handlers::lockout::user_key(&user_id);
+    let client_key = email_auth::handlers::lockout::client_key(&key, client);
+    email_auth::handlers::lockout::check(conn, &client_key, "username-or-email")?;
 
     if !Login::match_password(&user_data, &payload.password)? {
         // we intentionally send the error against username to avoid leaking the fact that the
         // username exists
         ft_sdk::println!("incorrect password");
+        email_auth::handlers::lockout::record_failure(conn, &client_key)?;
+        // failures from all clients, only used to tell the owner, see `lockout`
+        let failures = email_auth::handlers::lockout::count_failure(conn, &key)?;
+        if failures == email_auth::handlers::lockout::LOCKOUT_THRESHOLD {
+            email_auth::handlers::lockout::send_unlock_link(
+                conn, &user_id, user_data, app_url, config,
+            )?;
+        }
         return Err(
             ft_sdk::single_error("username-or-email", "Incorrect username/password.").into(),
         );
     }
 
-    Ok(Login { user_id })
+    email_auth::handlers::lockout::clear(conn, &client_key)?;
+    email_auth::handlers::lockout::clear(conn, &key)?;
+
+    let user_data = Login::rehash_if_needed(conn, &user_id, user_data, &payload.password, config)?;
+
+    Ok(Login {
+        two_factor: common::login::two_factor_enabled(Some(&user_data)),
+        user_id,
+    })
 }
 
 #[derive(serde::Deserialize, Debug)]
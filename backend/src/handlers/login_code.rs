@@ -0,0 +1,237 @@
+/// Wrong codes allowed before the code stops working and a new one has to be requested
+const MAX_CODE_ATTEMPTS: i64 = 5;
+
+/// Email a 6 digit sign-in code to the user, this is available on /backend/request-login-code/
+/// route
+///
+/// This is the same as `magic_link::request_magic_link`, but the user types the code in
+/// `login_with_code` instead of clicking a link. Some mail clients open links in emails to scan
+/// them, which burns single use links before the user gets to them. Requesting a new code
+/// invalidates the previous one.
+#[ft_sdk::form]
+pub fn request_login_code(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Required(email): ft_sdk::Required<"email">,
+    ft_sdk::Optional(next): ft_sdk::Optional<"next">,
+    client: common::session::ClientInfo,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let email = email.trim().to_string();
+
+    if !validator::ValidateEmail::validate_email(&email) {
+        return Err(ft_sdk::single_error("email", "Incorrect email format.").into());
+    }
+
+    let (user_id, mut data) =
+        match email_auth::utils::user_data_from_email_or_username(&mut conn, &email) {
+            Ok(v) => v,
+            Err(ft_sdk::auth::UserDataError::NoDataFound) => {
+                return Err(ft_sdk::single_error(
+                    "email",
+                    "No account is linked with the provided email",
+                )
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+    email_auth::utils::rate_limit_mail(&mut conn, "login-code", &email, &client, "email")?;
+
+    let code = common::code::generate();
+
+    let now = ft_sdk::env::now()
+        .timestamp_nanos_opt()
+        .expect("unexpected out of range datetime");
+
+    let custom = data.custom.as_object_mut().unwrap();
+    custom.insert(
+        email_auth::LOGIN_CODE_KEY.to_string(),
+        serde_json::Value::String(email_auth::handlers::create_account::hashed_password(
+            &code, &config,
+        )?),
+    );
+    custom.insert(
+        email_auth::LOGIN_CODE_SENT_AT.to_string(),
+        serde_json::Value::Number(now.into()),
+    );
+    custom.insert(
+        email_auth::LOGIN_CODE_ATTEMPTS.to_string(),
+        serde_json::Value::Number(0.into()),
+    );
+
+    let name = data.name.clone().unwrap_or_else(|| email.clone());
+
+    ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    send_login_code_email(email, name, &code, &config)?;
+
+    let next = next.unwrap_or_else(|| "/".to_string());
+    ft_sdk::form::redirect(next)
+}
+
+/// Log in with the code sent by `request_login_code`, this is available on
+/// /backend/login-with-code/ route
+///
+/// The code works only once, and only `MAX_CODE_ATTEMPTS` wrong codes are allowed before it stops
+/// working. Since the user proved they own the email, the email is marked verified too.
+#[ft_sdk::form]
+#[expect(clippy::too_many_arguments)]
+pub fn login_with_code(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Required(email): ft_sdk::Required<"email">,
+    ft_sdk::Required(code): ft_sdk::Required<"code">,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    host: ft_sdk::Host,
+    client: common::session::ClientInfo,
+    app_url: ft_sdk::AppUrl,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let email = email.trim().to_string();
+
+    let (user_id, mut data) =
+        match email_auth::utils::user_data_from_email_or_username(&mut conn, &email) {
+            Ok(v) => v,
+            Err(ft_sdk::auth::UserDataError::NoDataFound) => {
+                return Err(ft_sdk::single_error("code", "Incorrect code.").into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+    let hashed_code: String = data
+        .get_custom(email_auth::LOGIN_CODE_KEY)
+        .ok_or_else(|| ft_sdk::single_error("code", "Incorrect code."))?;
+
+    let sent_at = data
+        .get_custom(email_auth::LOGIN_CODE_SENT_AT)
+        .expect("login_code_sent_at should exists if the code exists");
+    let attempts: i64 = data
+        .get_custom(email_auth::LOGIN_CODE_ATTEMPTS)
+        .unwrap_or_default();
+
+    let error = if key_expired(chrono::DateTime::from_timestamp_nanos(sent_at)) {
+        Some("Code expired. Please request a new one.")
+    } else if attempts >= MAX_CODE_ATTEMPTS {
+        Some("Too many incorrect attempts. Please request a new code.")
+    } else {
+        None
+    };
+
+    if let Some(error) = error {
+        remove_code(&mut data);
+        ft_sdk::auth::provider::update_user(
+            &mut conn,
+            email_auth::PROVIDER_ID,
+            &user_id,
+            data,
+            false,
+        )?;
+        return Err(ft_sdk::single_error("code", error).into());
+    }
+
+    if !email_auth::handlers::create_account::verify_hashed_password(&hashed_code, code.trim())? {
+        data.custom.as_object_mut().unwrap().insert(
+            email_auth::LOGIN_CODE_ATTEMPTS.to_string(),
+            serde_json::Value::Number((attempts + 1).into()),
+        );
+        ft_sdk::auth::provider::update_user(
+            &mut conn,
+            email_auth::PROVIDER_ID,
+            &user_id,
+            data,
+            false,
+        )?;
+        return Err(ft_sdk::single_error("code", "Incorrect code.").into());
+    }
+
+    remove_code(&mut data);
+
+    if data.emails.contains(&email) && !data.verified_emails.contains(&email) {
+        data.verified_emails.push(email.clone());
+        data.custom
+            .as_object_mut()
+            .expect("custom is a json object")
+            .remove(email_auth::EMAIL_CONF_CODE_KEY);
+    }
+
+    let two_factor = common::login::two_factor_enabled(Some(&data));
+
+    ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    let next = next.unwrap_or_else(|| "/".to_string());
+
+    let (next, cookie) = common::login::complete_login(
+        &mut conn,
+        &user_id,
+        two_factor,
+        sid,
+        next,
+        host,
+        &client,
+        &app_url,
+        &config.session,
+    )?;
+
+    Ok(ft_sdk::form::redirect(next)?.with_cookie(cookie))
+}
+
+fn remove_code(data: &mut ft_sdk::auth::ProviderData) {
+    let custom = data
+        .custom
+        .as_object_mut()
+        .expect("custom is a json object");
+    custom.remove(email_auth::LOGIN_CODE_KEY);
+    custom.remove(email_auth::LOGIN_CODE_SENT_AT);
+    custom.remove(email_auth::LOGIN_CODE_ATTEMPTS);
+}
+
+pub fn send_login_code_email(
+    email: String,
+    name: String,
+    code: &str,
+    config: &crate::Config,
+) -> Result<(), ft_sdk::Error> {
+    let from = config.from_email();
+
+    ft_sdk::println!("Found email sender: {from:?},");
+
+    if let Err(e) = ft_sdk::email::send(&ft_sdk::Email {
+        from,
+        to: smallvec::smallvec![(name.clone(), email).into()],
+        reply_to: Some(smallvec::smallvec![config.reply_to()]),
+        cc: smallvec::smallvec![],
+        bcc: smallvec::smallvec![],
+        mkind: "login-code".to_string(),
+        content: ft_sdk::EmailContent::FromMKind {
+            context: Some(
+                serde_json::json!({
+                    "code": code,
+                    "name": name,
+                })
+                .as_object()
+                .unwrap()
+                .to_owned(),
+            ),
+        },
+    }) {
+        ft_sdk::println!("auth.wasm: failed to queue email: {:?}", e);
+        return Err(e.into());
+    }
+
+    ft_sdk::println!("Email added to the queue");
+
+    Ok(())
+}
+
+/// check if it has been 10 minutes since the code was sent. The threshold can be
+/// configured using LOGIN_CODE_EXPIRE_MINUTES env variable
+fn key_expired(sent_at: chrono::DateTime<chrono::Utc>) -> bool {
+    let expiry_limit_in_minutes: i64 = ft_sdk::env::var("LOGIN_CODE_EXPIRE_MINUTES".to_string())
+        .map(|v| {
+            v.parse()
+                .expect("LOGIN_CODE_EXPIRE_MINUTES should be a number")
+        })
+        .unwrap_or(10);
+
+    sent_at + chrono::TimeDelta::minutes(expiry_limit_in_minutes) <= ft_sdk::env::now()
+}
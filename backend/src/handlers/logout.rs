@@ -0,0 +1,20 @@
+/// Logout handler, this is available on /logout/ route
+///
+/// The `fastn_session` row of the current session is deleted, so the session id stops working
+/// on the server side as well, and the session cookie is cleared in the browser.
+#[ft_sdk::processor]
+pub fn logout(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    host: ft_sdk::Host,
+) -> ft_sdk::processor::Result {
+    if let Some(sid) = sid {
+        common::session::delete(&mut conn, &sid)?;
+        ft_sdk::println!("Session deleted for sid {sid}");
+    }
+
+    let next = next.unwrap_or_else(|| "/".to_string());
+    Ok(ft_sdk::processor::temporary_redirect(next)?
+        .with_cookie(common::expired_session_cookie(host)?))
+}
@@ -0,0 +1,223 @@
+/// Email a sign-in link to the user, this is available on /backend/request-magic-link/ route
+///
+/// The link logs the user in without a password, see `magic_login`. Requesting a new link
+/// invalidates the previous one.
+#[ft_sdk::form]
+pub fn request_magic_link(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Required(email): ft_sdk::Required<"email">,
+    ft_sdk::Optional(next): ft_sdk::Optional<"next">,
+    app_url: ft_sdk::AppUrl,
+    client: common::session::ClientInfo,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let email = email.trim().to_string();
+
+    if !validator::ValidateEmail::validate_email(&email) {
+        return Err(ft_sdk::single_error("email", "Incorrect email format.").into());
+    }
+
+    let (user_id, data) =
+        match email_auth::utils::user_data_from_email_or_username(&mut conn, &email) {
+            Ok(v) => v,
+            Err(ft_sdk::auth::UserDataError::NoDataFound) => {
+                return Err(ft_sdk::single_error(
+                    "email",
+                    "No account is linked with the provided email",
+                )
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+    email_auth::utils::rate_limit_mail(&mut conn, "magic-link", &email, &client, "email")?;
+
+    let name = data.name.clone().unwrap_or_else(|| email.clone());
+
+    let magic_login_url = app_url.join("/backend/magic-login/").inspect_err(|e| {
+        ft_sdk::println!("auth.wasm: failed to join url: {:?}", e);
+    })?;
+
+    let link = generate_new_magic_link(data, &user_id, &email, magic_login_url, &mut conn)?;
+
+    send_magic_link_email(email, name, &link, &config)?;
+
+    let next = next.unwrap_or_else(|| "/".to_string());
+    ft_sdk::form::redirect(next)
+}
+
+/// Log in the user the magic link was sent to, this is available on /backend/magic-login/ route
+///
+/// The code works only once. Since the user proved they own the email by clicking the link, the
+/// email is marked verified too. Users with two-factor authentication still have to enter their
+/// code.
+#[ft_sdk::processor]
+#[expect(clippy::too_many_arguments)]
+pub fn magic_login(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Query(code): ft_sdk::Query<"code">,
+    ft_sdk::Query(email): ft_sdk::Query<"email">,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    host: ft_sdk::Host,
+    client: common::session::ClientInfo,
+    app_url: ft_sdk::AppUrl,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::processor::Result {
+    let (user_id, mut data) = match ft_sdk::auth::provider::user_data_by_custom_attribute(
+        &mut conn,
+        email_auth::PROVIDER_ID,
+        email_auth::MAGIC_LINK_CODE_KEY,
+        &code,
+    ) {
+        Ok(v) => v,
+        Err(ft_sdk::auth::UserDataError::NoDataFound) => {
+            return Err(ft_sdk::single_error("code", "Invalid or already used link.").into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if !data.emails.contains(&email) {
+        return Err(
+            ft_sdk::single_error("email", "Provided email not found for this user.").into(),
+        );
+    }
+
+    let sent_at = data
+        .get_custom(email_auth::MAGIC_LINK_CODE_SENT_AT)
+        .expect("magic_link_code_sent_at should exists if the account was found");
+
+    let expired = key_expired(chrono::DateTime::from_timestamp_nanos(sent_at));
+
+    // the code is single use, it goes even if it has expired
+    let custom = data
+        .custom
+        .as_object_mut()
+        .expect("custom is a json object");
+    custom.remove(email_auth::MAGIC_LINK_CODE_KEY);
+    custom.remove(email_auth::MAGIC_LINK_CODE_SENT_AT);
+
+    if !expired && !data.verified_emails.contains(&email) {
+        data.verified_emails.push(email.clone());
+        data.custom
+            .as_object_mut()
+            .expect("custom is a json object")
+            .remove(email_auth::EMAIL_CONF_CODE_KEY);
+    }
+
+    let two_factor = common::login::two_factor_enabled(Some(&data));
+
+    ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    if expired {
+        return Err(ft_sdk::single_error(
+            "code",
+            "The link has expired. Please request a new one.",
+        )
+        .into());
+    }
+
+    let next = next.unwrap_or_else(|| "/".to_string());
+
+    let (next, cookie) = common::login::complete_login(
+        &mut conn,
+        &user_id,
+        two_factor,
+        sid,
+        next,
+        host,
+        &client,
+        &app_url,
+        &config.session,
+    )?;
+
+    Ok(ft_sdk::processor::temporary_redirect(next)?.with_cookie(cookie))
+}
+
+/// Generate a new magic link code for a given email and update the user table
+pub fn generate_new_magic_link(
+    mut data: ft_sdk::auth::ProviderData,
+    user_id: &ft_sdk::auth::UserId,
+    email: &str,
+    magic_login_url: String,
+    conn: &mut ft_sdk::Connection,
+) -> ft_sdk::Result<String> {
+    let key = ft_sdk::Rng::generate_key(64);
+
+    let link = format!(
+        "{magic_login_url}?code={key}&email={}",
+        common::fetch::url_encode(email)
+    );
+
+    // the link logs whoever has it in, so only the email is logged
+    ft_sdk::println!("Magic link added for {email}");
+
+    data.custom.as_object_mut().unwrap().insert(
+        email_auth::MAGIC_LINK_CODE_KEY.to_string(),
+        serde_json::Value::String(key),
+    );
+
+    let now = ft_sdk::env::now()
+        .timestamp_nanos_opt()
+        .expect("unexpected out of range datetime");
+
+    data.custom.as_object_mut().unwrap().insert(
+        email_auth::MAGIC_LINK_CODE_SENT_AT.to_string(),
+        serde_json::Value::Number(now.into()),
+    );
+
+    ft_sdk::auth::provider::update_user(conn, email_auth::PROVIDER_ID, user_id, data, false)?;
+
+    Ok(link)
+}
+
+pub fn send_magic_link_email(
+    email: String,
+    name: String,
+    link: &str,
+    config: &crate::Config,
+) -> Result<(), ft_sdk::Error> {
+    let from = config.from_email();
+
+    ft_sdk::println!("Found email sender: {from:?},");
+
+    if let Err(e) = ft_sdk::email::send(&ft_sdk::Email {
+        from,
+        to: smallvec::smallvec![(name.clone(), email).into()],
+        reply_to: Some(smallvec::smallvec![config.reply_to()]),
+        cc: smallvec::smallvec![],
+        bcc: smallvec::smallvec![],
+        mkind: "magic-link".to_string(),
+        content: ft_sdk::EmailContent::FromMKind {
+            context: Some(
+                serde_json::json!({
+                    "link": link,
+                    "name": name,
+                })
+                .as_object()
+                .unwrap()
+                .to_owned(),
+            ),
+        },
+    }) {
+        ft_sdk::println!("auth.wasm: failed to queue email: {:?}", e);
+        return Err(e.into());
+    }
+
+    ft_sdk::println!("Email added to the queue");
+
+    Ok(())
+}
+
+/// check if it has been 15 minutes since the magic link was sent. The threshold can be
+/// configured using MAGIC_LINK_EXPIRE_MINUTES env variable
+fn key_expired(sent_at: chrono::DateTime<chrono::Utc>) -> bool {
+    let expiry_limit_in_minutes: i64 = ft_sdk::env::var("MAGIC_LINK_EXPIRE_MINUTES".to_string())
+        .map(|v| {
+            v.parse()
+                .expect("MAGIC_LINK_EXPIRE_MINUTES should be a number")
+        })
+        .unwrap_or(15);
+
+    sent_at + chrono::TimeDelta::minutes(expiry_limit_in_minutes) <= ft_sdk::env::now()
+}
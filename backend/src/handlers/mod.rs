@@ -1,8 +1,17 @@
 pub mod confirm_email;
 pub mod create_account;
 pub mod forgot_password;
+pub mod github;
+pub mod import_users;
+pub mod linked_providers;
+pub mod lockout;
 pub mod login;
+pub mod login_code;
+pub mod logout;
+pub mod magic_link;
 pub mod resend_confirmation_email;
+pub mod sessions;
 pub mod set_password;
+pub mod two_factor;
 pub mod user_data_by_code;
 pub(crate) mod utils;
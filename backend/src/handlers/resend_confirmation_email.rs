@@ -1,17 +1,25 @@
 #[ft_sdk::processor]
-// TODO: add a rate limit to this endpoint
 pub fn resend_confirmation_email(
     mut conn: ft_sdk::Connection,
     ft_sdk::Query(email): ft_sdk::Query<"email">,
     ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
     host: ft_sdk::Host,
     app_url: ft_sdk::AppUrl,
+    client: common::session::ClientInfo,
     ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
 ) -> ft_sdk::processor::Result {
     if !validator::ValidateEmail::validate_email(&email) {
         return Err(ft_sdk::single_error("email", "Incorrect email format.").into());
     }
 
+    email_auth::utils::rate_limit_mail(
+        &mut conn,
+        "resend-confirmation-email",
+        &email,
+        &client,
+        "email",
+    )?;
+
     let (user_id, data) =
         ft_sdk::auth::provider::user_data_by_email(&mut conn, email_auth::PROVIDER_ID, &email)?;
 
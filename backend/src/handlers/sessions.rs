@@ -0,0 +1,55 @@
+/// List the active sessions of the logged in user, this is available on /sessions/ route
+///
+/// The session ids are never sent out, every session carries a `handle` instead which can be
+/// passed to `revoke_session`.
+#[ft_sdk::data]
+pub fn sessions(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::data::Result {
+    let (user_id, sid) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+
+    ft_sdk::data::json(common::session::list(&mut conn, &user_id, &sid)?)
+}
+
+/// Revoke a session of the logged in user, this is available on /revoke-session/ route
+///
+/// If `handle` is not provided, every session of the user except the current one is revoked
+/// ("log out everywhere").
+#[ft_sdk::form]
+pub fn revoke_session(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Form(payload): ft_sdk::Form<RevokeSessionPayload>,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    host: ft_sdk::Host,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let (user_id, sid) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+    let next = next.unwrap_or_else(|| "/".to_string());
+
+    let handle = match payload.handle {
+        Some(handle) => handle,
+        None => {
+            let count = common::session::delete_all_except(&mut conn, &user_id, Some(&sid))?;
+            ft_sdk::println!("Revoked {count} other sessions of user {}", user_id.0);
+            return ft_sdk::form::redirect(next);
+        }
+    };
+
+    match common::session::delete_by_handle(&mut conn, &user_id, handle)? {
+        // the user revoked the session they are using right now, log them out here as well
+        Some(deleted) if deleted == sid => {
+            Ok(ft_sdk::form::redirect(next)?.with_cookie(common::expired_session_cookie(host)?))
+        }
+        Some(_) => ft_sdk::form::redirect(next),
+        None => Err(ft_sdk::single_error("handle", "Session not found.").into()),
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct RevokeSessionPayload {
+    handle: Option<i64>,
+}
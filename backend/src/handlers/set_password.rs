@@ -7,15 +7,17 @@ pub fn set_password(
     ft_sdk::Query(code): ft_sdk::Query<"code", Option<String>>,
     ft_sdk::Query(email): ft_sdk::Query<"email", Option<String>>,
     ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Optional(keep_other_sessions): ft_sdk::Optional<"keep-other-sessions">,
     app_url: ft_sdk::AppUrl,
     sid: ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
     ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
 ) -> ft_sdk::form::Result {
-    validate_email_and_password(&email, &new_password, &new_password2)?;
+    validate_email_and_password(&email, &new_password, &new_password2, &config)?;
 
     let next = next.unwrap_or_else(|| "/".to_string());
+    let current_sid = sid.0.clone();
 
-    let (user_id, data) = get_user(&mut conn, sid, code)?;
+    let (user_id, data, logged_in) = get_user(&mut conn, sid, code, &config)?;
 
     let sent_at = data.get_custom(email_auth::PASSWORD_RESET_CODE_SENT_AT);
 
@@ -31,7 +33,7 @@ pub fn set_password(
             user_id.clone(),
             email,
             &mut conn,
-            config,
+            &config,
         )?;
     }
 
@@ -45,7 +47,8 @@ pub fn set_password(
                 "hashed_password".to_string(),
                 serde_json::Value::String(email_auth::handlers::create_account::hashed_password(
                     &new_password,
-                )),
+                    &config,
+                )?),
             );
 
         data.custom
@@ -57,6 +60,22 @@ pub fn set_password(
     };
 
     ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    // the user has proven they own the account, failed login attempts no longer matter
+    email_auth::handlers::lockout::clear(
+        &mut conn,
+        &email_auth::handlers::lockout::user_key(&user_id),
+    )?;
+
+    // A reset through a code means the user may have lost control of their account, so all their
+    // other sessions go. A logged in user changing their password can choose to keep the other
+    // devices signed in.
+    if !logged_in || keep_other_sessions.as_deref() != Some("true") {
+        let count =
+            common::session::delete_all_except(&mut conn, &user_id, current_sid.as_deref())?;
+        ft_sdk::println!("Revoked {count} other sessions after password change");
+    }
+
     ft_sdk::form::redirect(next)
 }
 
@@ -80,6 +99,7 @@ fn validate_email_and_password(
     email: &Option<String>,
     new_password: &str,
     new_password2: &str,
+    config: &crate::Config,
 ) -> Result<(), ft_sdk::Error> {
     if email.is_some() && !validator::ValidateEmail::validate_email(email.as_ref().unwrap()) {
         return Err(ft_sdk::single_error("email", "Invalid email format.").into());
@@ -93,20 +113,27 @@ fn validate_email_and_password(
         .into());
     }
 
-    if let Some(message) = email_auth::handlers::create_account::is_strong_password(new_password) {
+    if let Some(message) = email_auth::password_policy::check(new_password, &config.password_policy)
+    {
         return Err(ft_sdk::single_error("new-password", message).into());
     }
 
     Ok(())
 }
 
-/// Get logged in user or user with the reset code
+/// Get logged in user or user with the reset code. The last item of the returned tuple is true if
+/// the user is logged in.
 fn get_user(
     conn: &mut ft_sdk::Connection,
     sid: ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
     reset_code: Option<String>,
-) -> Result<(ft_sdk::UserId, ft_sdk::auth::ProviderData), ft_sdk::Error> {
-    let user = ft_sdk::auth::ud(sid, conn).ok().flatten().map(|v| v.id);
+    config: &crate::Config,
+) -> Result<(ft_sdk::UserId, ft_sdk::auth::ProviderData, bool), ft_sdk::Error> {
+    let user = common::session::ud(conn, sid.0, &config.session)
+        .ok()
+        .flatten()
+        .map(|v| v.id);
+    let logged_in = user.is_some();
 
     let res = if let Some(user_id) = user {
         // if user is logged in, we can use the user_id to get the user data
@@ -130,7 +157,7 @@ fn get_user(
     };
 
     match res {
-        Ok(v) => Ok(v),
+        Ok((user_id, data)) => Ok((user_id, data, logged_in)),
         Err(ft_sdk::auth::UserDataError::NoDataFound) => {
             Err(ft_sdk::single_error("code", "Invalid reset code or not logged in.").into())
         }
@@ -145,7 +172,7 @@ fn check_expired_and_send_reset_link(
     user_id: ft_sdk::UserId,
     email: Option<String>,
     conn: &mut ft_sdk::Connection,
-    config: crate::Config,
+    config: &crate::Config,
 ) -> Result<(), ft_sdk::Error> {
     let sent_at = chrono::DateTime::from_timestamp_nanos(sent_at);
 
@@ -171,7 +198,7 @@ fn check_expired_and_send_reset_link(
         email.to_string(),
         name,
         &reset_link,
-        &config,
+        config,
     )?;
 
     Err(ft_sdk::single_error(
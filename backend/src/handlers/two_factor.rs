@@ -0,0 +1,614 @@
+/// A pending login has to be completed in these many minutes
+const PENDING_LOGIN_EXPIRE_MINUTES: i64 = 10;
+/// Number of recovery codes handed out at a time
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Key in `fastn_session.data` that holds the codes made by `regenerate_recovery_codes`
+const NEW_RECOVERY_CODES_KEY: &str = "new-recovery-codes";
+/// `new_recovery_codes` shows the new codes for these many minutes
+const NEW_RECOVERY_CODES_EXPIRE_MINUTES: i64 = 10;
+
+/// Recovery codes made by `regenerate_recovery_codes`, encrypted like the TOTP secret
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct NewRecoveryCodes {
+    codes: String,
+    created_at: i64,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct TwoFactorSetup {
+    secret: String,
+    otpauth_url: String,
+    recovery_codes: Vec<String>,
+}
+
+/// Start TOTP enrolment for the logged in user, this is available on /start-two-factor-setup/
+/// route
+///
+/// A new secret and a fresh set of recovery codes are generated and stored as pending, replacing
+/// any earlier pending setup. The user is then sent to `next`, which shows them with
+/// `two_factor_setup`. The secret only gets used for login once the user proves they have set it
+/// up by submitting a code to `enable_two_factor`.
+#[ft_sdk::form]
+pub fn start_two_factor_setup(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let (user_id, _) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+    let mut data =
+        ft_sdk::auth::provider::user_data_by_id(&mut conn, email_auth::PROVIDER_ID, &user_id)?;
+
+    if common::login::two_factor_enabled(Some(&data)) {
+        return Err(
+            ft_sdk::single_error("code", "Two-factor authentication is already enabled.").into(),
+        );
+    }
+
+    let secret = ft_sdk::Rng::generate_key(32).into_bytes();
+    let recovery_codes = generate_recovery_codes();
+
+    let custom = data
+        .custom
+        .as_object_mut()
+        .expect("custom is a json object");
+    custom.insert(
+        email_auth::TOTP_PENDING_SECRET_KEY.to_string(),
+        serde_json::Value::String(encrypt(&secret)?),
+    );
+    // kept encrypted, not hashed, till the setup is confirmed so `two_factor_setup` can show them
+    custom.insert(
+        email_auth::TOTP_PENDING_RECOVERY_CODES_KEY.to_string(),
+        serde_json::Value::String(encrypt(&serde_json::to_vec(&recovery_codes)?)?),
+    );
+
+    ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    ft_sdk::form::redirect(next.unwrap_or_else(|| "/".to_string()))
+}
+
+/// The pending TOTP setup of the logged in user, this is available on /two-factor-setup/ route
+///
+/// Returns the secret made by `start_two_factor_setup`, along with an `otpauth://` url to render
+/// as QR code, and the recovery codes that come with it. Nothing is changed here, the same setup
+/// is returned till it is confirmed with `enable_two_factor` or replaced by a new one.
+#[ft_sdk::data]
+pub fn two_factor_setup(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    host: ft_sdk::Host,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::data::Result {
+    let (user_id, _) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+    let data =
+        ft_sdk::auth::provider::user_data_by_id(&mut conn, email_auth::PROVIDER_ID, &user_id)?;
+
+    let (Some(secret), Some(recovery_codes)) = (
+        data.get_custom::<String>(email_auth::TOTP_PENDING_SECRET_KEY),
+        data.get_custom::<String>(email_auth::TOTP_PENDING_RECOVERY_CODES_KEY),
+    ) else {
+        return Err(ft_sdk::single_error("code", "Two-factor setup has not been started.").into());
+    };
+
+    let secret = totp_rs::Secret::Raw(decrypt(&secret)?)
+        .to_encoded()
+        .to_string();
+    let recovery_codes: Vec<String> = serde_json::from_slice(&decrypt(&recovery_codes)?)?;
+
+    let account = data.first_email().unwrap_or_else(|| data.identity.clone());
+
+    ft_sdk::data::json(TwoFactorSetup {
+        otpauth_url: otpauth_url(&host.without_port(), &account, &secret),
+        secret,
+        recovery_codes,
+    })
+}
+
+/// Finish TOTP enrolment, this is available on /enable-two-factor/ route
+///
+/// The `code` must be generated using the secret handed out by `two_factor_setup`.
+#[ft_sdk::form]
+pub fn enable_two_factor(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Required(code): ft_sdk::Required<"code">,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let (user_id, _) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+    let mut data =
+        ft_sdk::auth::provider::user_data_by_id(&mut conn, email_auth::PROVIDER_ID, &user_id)?;
+
+    let pending: String = data
+        .get_custom(email_auth::TOTP_PENDING_SECRET_KEY)
+        .ok_or_else(|| ft_sdk::single_error("code", "Two-factor setup has not been started."))?;
+
+    if !accept_code(&mut data, &decrypt(&pending)?, &code)? {
+        return Err(ft_sdk::single_error("code", "Incorrect code.").into());
+    }
+
+    let custom = data
+        .custom
+        .as_object_mut()
+        .expect("custom is a json object");
+    custom.remove(email_auth::TOTP_PENDING_SECRET_KEY);
+    custom.insert(
+        email_auth::TOTP_SECRET_KEY.to_string(),
+        serde_json::Value::String(pending),
+    );
+    if let Some(serde_json::Value::String(codes)) =
+        custom.remove(email_auth::TOTP_PENDING_RECOVERY_CODES_KEY)
+    {
+        let codes: Vec<String> = serde_json::from_slice(&decrypt(&codes)?)?;
+        custom.insert(
+            email_auth::TOTP_RECOVERY_CODES_KEY.to_string(),
+            hashed_recovery_codes(&codes, &config)?,
+        );
+    }
+
+    ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    ft_sdk::form::redirect(next.unwrap_or_else(|| "/".to_string()))
+}
+
+/// Turn off two-factor authentication for the logged in user, this is available on
+/// /disable-two-factor/ route. A valid `code`, or a recovery code, is required. Wrong codes count
+/// towards the same `lockout::two_factor_key` as `verify_two_factor`, so a stolen session can not
+/// be used to guess the code.
+#[ft_sdk::form]
+pub fn disable_two_factor(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Required(code): ft_sdk::Required<"code">,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let (user_id, _) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+    let key = email_auth::handlers::lockout::two_factor_key(&user_id);
+    email_auth::handlers::lockout::check(&mut conn, &key, "code")?;
+
+    let mut data =
+        ft_sdk::auth::provider::user_data_by_id(&mut conn, email_auth::PROVIDER_ID, &user_id)?;
+
+    if !verify(&mut conn, &user_id, &mut data, &code, &config)? {
+        email_auth::handlers::lockout::record_failure(&mut conn, &key)?;
+        return Err(ft_sdk::single_error("code", "Incorrect code.").into());
+    }
+
+    email_auth::handlers::lockout::clear(&mut conn, &key)?;
+
+    let custom = data
+        .custom
+        .as_object_mut()
+        .expect("custom is a json object");
+    custom.remove(email_auth::TOTP_SECRET_KEY);
+    custom.remove(email_auth::TOTP_PENDING_SECRET_KEY);
+    custom.remove(email_auth::TOTP_LAST_STEP_KEY);
+    custom.remove(email_auth::TOTP_RECOVERY_CODES_KEY);
+    custom.remove(email_auth::TOTP_PENDING_RECOVERY_CODES_KEY);
+
+    ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    ft_sdk::form::redirect(next.unwrap_or_else(|| "/".to_string()))
+}
+
+/// Replace the recovery codes of the logged in user, this is available on
+/// /regenerate-recovery-codes/ route
+///
+/// All existing recovery codes stop working. A valid TOTP `code` is required, and wrong codes
+/// count towards `lockout::two_factor_key`, so a stolen session alone can not be used to get
+/// recovery codes. The new codes are kept, encrypted, in the session
+/// for `NEW_RECOVERY_CODES_EXPIRE_MINUTES` so `new_recovery_codes` can show them, and the user is
+/// sent to `next`.
+#[ft_sdk::form]
+pub fn regenerate_recovery_codes(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Required(code): ft_sdk::Required<"code">,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let (user_id, sid) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+    let key = email_auth::handlers::lockout::two_factor_key(&user_id);
+    email_auth::handlers::lockout::check(&mut conn, &key, "code")?;
+
+    let mut data =
+        ft_sdk::auth::provider::user_data_by_id(&mut conn, email_auth::PROVIDER_ID, &user_id)?;
+
+    let secret: String = data
+        .get_custom(email_auth::TOTP_SECRET_KEY)
+        .ok_or_else(|| ft_sdk::single_error("code", "Two-factor authentication is not enabled."))?;
+
+    if !accept_code(&mut data, &decrypt(&secret)?, &code)? {
+        email_auth::handlers::lockout::record_failure(&mut conn, &key)?;
+        return Err(ft_sdk::single_error("code", "Incorrect code.").into());
+    }
+
+    email_auth::handlers::lockout::clear(&mut conn, &key)?;
+
+    let recovery_codes = generate_recovery_codes();
+
+    data.custom
+        .as_object_mut()
+        .expect("custom is a json object")
+        .insert(
+            email_auth::TOTP_RECOVERY_CODES_KEY.to_string(),
+            hashed_recovery_codes(&recovery_codes, &config)?,
+        );
+
+    ft_sdk::auth::provider::update_user(&mut conn, email_auth::PROVIDER_ID, &user_id, data, false)?;
+
+    common::session::set_data(
+        &mut conn,
+        &sid,
+        NEW_RECOVERY_CODES_KEY,
+        &NewRecoveryCodes {
+            codes: encrypt(&serde_json::to_vec(&recovery_codes)?)?,
+            created_at: ft_sdk::env::now()
+                .timestamp_nanos_opt()
+                .expect("unexpected out of range datetime"),
+        },
+    )?;
+
+    ft_sdk::form::redirect(next.unwrap_or_else(|| "/".to_string()))
+}
+
+/// The recovery codes just made by `regenerate_recovery_codes`, this is available on
+/// /new-recovery-codes/ route. Only the session that made them can see them, and only for
+/// `NEW_RECOVERY_CODES_EXPIRE_MINUTES`.
+#[ft_sdk::data]
+pub fn new_recovery_codes(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::data::Result {
+    let (_, sid) = email_auth::utils::logged_in_user(&mut conn, sid, &config)?;
+
+    let new: Option<NewRecoveryCodes> =
+        common::session::get_data(&mut conn, &sid, NEW_RECOVERY_CODES_KEY)?;
+
+    let recovery_codes: Vec<String> = match new {
+        Some(new)
+            if chrono::DateTime::from_timestamp_nanos(new.created_at)
+                + chrono::TimeDelta::minutes(NEW_RECOVERY_CODES_EXPIRE_MINUTES)
+                > ft_sdk::env::now() =>
+        {
+            serde_json::from_slice(&decrypt(&new.codes)?)?
+        }
+        _ => vec![],
+    };
+
+    ft_sdk::data::json(serde_json::json!({ "recovery-codes": recovery_codes }))
+}
+
+/// Second step of login for users with two-factor authentication, this is available on
+/// /verify-two-factor/ route
+///
+/// `common::login::complete_login` leaves a `PendingLogin` in the session, the user is logged in
+/// once they submit a valid `code`. A recovery code can be used in place of the TOTP code. Wrong
+/// codes are counted against the user, not the pending login, see `lockout::two_factor_key`: a
+/// new pending login is only a password away, so a count kept there would not limit anything.
+#[ft_sdk::form]
+pub fn verify_two_factor(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Required(code): ft_sdk::Required<"code">,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    host: ft_sdk::Host,
+    client: common::session::ClientInfo,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::form::Result {
+    let login_again =
+        || ft_sdk::single_error("code", "Your login has expired, please login again.");
+
+    let sid = common::session::reusable(&mut conn, sid, &config.session)?
+        .ok_or_else(login_again)?
+        .0;
+
+    let pending: common::login::PendingLogin =
+        common::session::get_data(&mut conn, &sid, common::login::PENDING_LOGIN_KEY)?
+            .ok_or_else(login_again)?;
+
+    let started_at = chrono::DateTime::from_timestamp_nanos(pending.started_at);
+    if started_at + chrono::TimeDelta::minutes(PENDING_LOGIN_EXPIRE_MINUTES) <= ft_sdk::env::now() {
+        common::session::remove_data(&mut conn, &sid, common::login::PENDING_LOGIN_KEY)?;
+        return Err(login_again().into());
+    }
+
+    let user_id = ft_sdk::UserId(pending.uid);
+    let key = email_auth::handlers::lockout::two_factor_key(&user_id);
+    email_auth::handlers::lockout::check(&mut conn, &key, "code")?;
+
+    let mut data =
+        ft_sdk::auth::provider::user_data_by_id(&mut conn, email_auth::PROVIDER_ID, &user_id)?;
+
+    if !verify(&mut conn, &user_id, &mut data, &code, &config)? {
+        email_auth::handlers::lockout::record_failure(&mut conn, &key)?;
+        return Err(ft_sdk::single_error("code", "Incorrect code.").into());
+    }
+
+    email_auth::handlers::lockout::clear(&mut conn, &key)?;
+    common::session::remove_data(&mut conn, &sid, common::login::PENDING_LOGIN_KEY)?;
+
+    let ft_sdk::SessionID(sid) =
+        ft_sdk::auth::provider::login(&mut conn, &user_id, Some(ft_sdk::SessionID(sid)))?;
+    common::session::on_login(&mut conn, &sid, &client, &config.session)?;
+
+    let cookie = common::session_cookie(sid.as_str(), host, &config.session)?;
+    Ok(ft_sdk::form::redirect(pending.next)?.with_cookie(cookie))
+}
+
+/// Check `code` against the TOTP secret of the user. If it is not a valid TOTP code, it is tried
+/// as a recovery code. A matching recovery code is consumed, and the user is told about it by
+/// email, so they notice if someone else is using their codes.
+fn verify(
+    conn: &mut ft_sdk::Connection,
+    user_id: &ft_sdk::UserId,
+    data: &mut ft_sdk::auth::ProviderData,
+    code: &str,
+    config: &crate::Config,
+) -> Result<bool, ft_sdk::Error> {
+    let secret: String = data
+        .get_custom(email_auth::TOTP_SECRET_KEY)
+        .ok_or_else(|| ft_sdk::single_error("code", "Two-factor authentication is not enabled."))?;
+
+    if accept_code(data, &decrypt(&secret)?, code)? {
+        ft_sdk::auth::provider::update_user(
+            conn,
+            email_auth::PROVIDER_ID,
+            user_id,
+            data.clone(),
+            false,
+        )?;
+        return Ok(true);
+    }
+
+    let mut hashes: Vec<String> = data
+        .get_custom(email_auth::TOTP_RECOVERY_CODES_KEY)
+        .unwrap_or_default();
+
+    let code = normalise_recovery_code(code);
+    let mut used = None;
+    for (i, hash) in hashes.iter().enumerate() {
+        if email_auth::handlers::create_account::verify_hashed_password(hash, &code)? {
+            used = Some(i);
+            break;
+        }
+    }
+
+    let Some(used) = used else {
+        return Ok(false);
+    };
+
+    hashes.remove(used);
+    let remaining = hashes.len();
+
+    data.custom
+        .as_object_mut()
+        .expect("custom is a json object")
+        .insert(
+            email_auth::TOTP_RECOVERY_CODES_KEY.to_string(),
+            serde_json::json!(hashes),
+        );
+    ft_sdk::auth::provider::update_user(
+        conn,
+        email_auth::PROVIDER_ID,
+        user_id,
+        data.clone(),
+        false,
+    )?;
+
+    if let Some(email) = data.first_email() {
+        let name = data.name.clone().unwrap_or_else(|| email.clone());
+        send_recovery_code_used_email(email, name, remaining, config)?;
+    }
+
+    Ok(true)
+}
+
+/// Recovery codes look like `abcde-12345`, case and the dash are ignored when checking them
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let key = ft_sdk::Rng::generate_key(10).to_lowercase();
+            format!("{}-{}", &key[..5], &key[5..])
+        })
+        .collect()
+}
+
+fn normalise_recovery_code(code: &str) -> String {
+    let code: String = code
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+
+    if code.len() == 10 {
+        format!("{}-{}", &code[..5], &code[5..])
+    } else {
+        code
+    }
+}
+
+/// Recovery codes are stored argon2 hashed, same as passwords
+fn hashed_recovery_codes(
+    codes: &[String],
+    config: &crate::Config,
+) -> Result<serde_json::Value, ft_sdk::Error> {
+    Ok(serde_json::json!(
+        codes
+            .iter()
+            .map(|c| email_auth::handlers::create_account::hashed_password(c, config))
+            .collect::<Result<Vec<_>, _>>()?
+    ))
+}
+
+pub fn send_recovery_code_used_email(
+    email: String,
+    name: String,
+    remaining: usize,
+    config: &crate::Config,
+) -> Result<(), ft_sdk::Error> {
+    let from = config.from_email();
+
+    ft_sdk::println!("Found email sender: {from:?},");
+
+    if let Err(e) = ft_sdk::email::send(&ft_sdk::Email {
+        from,
+        to: smallvec::smallvec![(name.clone(), email).into()],
+        reply_to: Some(smallvec::smallvec![config.reply_to()]),
+        cc: smallvec::smallvec![],
+        bcc: smallvec::smallvec![],
+        mkind: "recovery-code-used".to_string(),
+        content: ft_sdk::EmailContent::FromMKind {
+            context: Some(
+                serde_json::json!({
+                    "name": name,
+                    "remaining": remaining.to_string(),
+                })
+                .as_object()
+                .unwrap()
+                .to_owned(),
+            ),
+        },
+    }) {
+        ft_sdk::println!("auth.wasm: failed to queue email: {:?}", e);
+        return Err(e.into());
+    }
+
+    ft_sdk::println!("Email added to the queue");
+
+    Ok(())
+}
+
+/// Check a TOTP `code` against `secret`. A code is accepted only once: the step it was generated
+/// for is stored in `data`, and codes of that step or an earlier one are refused from then on. The
+/// caller saves `data`.
+fn accept_code(
+    data: &mut ft_sdk::auth::ProviderData,
+    secret: &[u8],
+    code: &str,
+) -> Result<bool, ft_sdk::Error> {
+    let now = ft_sdk::env::now().timestamp() as u64;
+
+    let Some(step) = code_step(secret, code, now)? else {
+        return Ok(false);
+    };
+
+    if data
+        .get_custom::<u64>(email_auth::TOTP_LAST_STEP_KEY)
+        .is_some_and(|last| step <= last)
+    {
+        ft_sdk::println!("totp code reused");
+        return Ok(false);
+    }
+
+    data.custom
+        .as_object_mut()
+        .expect("custom is a json object")
+        .insert(email_auth::TOTP_LAST_STEP_KEY.to_string(), step.into());
+
+    Ok(true)
+}
+
+/// The 30 second step `code` was generated for, if it is a valid code for `secret` at `now`
+fn code_step(secret: &[u8], code: &str, now: u64) -> Result<Option<u64>, ft_sdk::Error> {
+    let totp = totp_rs::TOTP::new(totp_rs::Algorithm::SHA1, 6, 1, 30, secret.to_vec())
+        .map_err(|e| ft_sdk::server_error!("invalid totp secret: {e:?}"))?;
+
+    let code = code.trim();
+
+    // the codes of the previous and the next step are accepted too, to make up for clock drift on
+    // the user's device
+    Ok([now.saturating_sub(30), now, now + 30]
+        .into_iter()
+        .find(|t| {
+            email_auth::password_hash::constant_time_eq(
+                totp.generate(*t).as_bytes(),
+                code.as_bytes(),
+            )
+        })
+        .map(|t| t / 30))
+}
+
+/// `otpauth://` url of a TOTP `secret` (base32) for `account` on `issuer`, authenticator apps
+/// read it from a QR code
+fn otpauth_url(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = common::fetch::url_encode(issuer);
+
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits=6&period=30",
+        account = common::fetch::url_encode(account),
+    )
+}
+
+/// The cipher used to store TOTP secrets. The key comes from the TWO_FACTOR_ENCRYPTION_KEY env
+/// variable, so a leaked database alone is not enough to generate codes.
+fn cipher() -> Result<chacha20poly1305::ChaCha20Poly1305, ft_sdk::Error> {
+    use chacha20poly1305::KeyInit;
+    use sha2::Digest;
+
+    let key = ft_sdk::env::var("TWO_FACTOR_ENCRYPTION_KEY".to_string())
+        .ok_or_else(|| ft_sdk::server_error!("TWO_FACTOR_ENCRYPTION_KEY is not set"))?;
+
+    Ok(chacha20poly1305::ChaCha20Poly1305::new(
+        &sha2::Sha256::digest(key.as_bytes()),
+    ))
+}
+
+/// Encrypt `secret`, the output is base64 encoded nonce followed by the ciphertext
+fn encrypt(secret: &[u8]) -> Result<String, ft_sdk::Error> {
+    use base64::Engine;
+    use chacha20poly1305::aead::{Aead, AeadCore};
+
+    let nonce = chacha20poly1305::ChaCha20Poly1305::generate_nonce(&mut ft_sdk::Rng {});
+    let ciphertext = cipher()?
+        .encrypt(&nonce, secret)
+        .map_err(|e| ft_sdk::server_error!("failed to encrypt totp secret: {e:?}"))?;
+
+    let mut out = nonce.to_vec();
+    out.extend(ciphertext);
+
+    Ok(base64::engine::general_purpose::STANDARD.encode(out))
+}
+
+fn decrypt(encrypted: &str) -> Result<Vec<u8>, ft_sdk::Error> {
+    use base64::Engine;
+    use chacha20poly1305::aead::Aead;
+
+    let bytes = base64::engine::general_purpose::STANDARD.decode(encrypted)?;
+    if bytes.len() < 12 {
+        return Err(ft_sdk::server_error!("encrypted totp secret is too short").into());
+    }
+
+    let (nonce, ciphertext) = bytes.split_at(12);
+
+    cipher()?
+        .decrypt(chacha20poly1305::Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| ft_sdk::server_error!("failed to decrypt totp secret: {e:?}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_code_step() {
+        // RFC 6238 test secret, its 8 digit code at time 59 is 94287082
+        let secret = b"12345678901234567890";
+
+        assert_eq!(super::code_step(secret, "287082", 59).unwrap(), Some(1));
+        // the code of the previous step is still accepted, it keeps its own step
+        assert_eq!(super::code_step(secret, " 287082 ", 89).unwrap(), Some(1));
+        assert_eq!(super::code_step(secret, "287082", 120).unwrap(), None);
+        assert_eq!(super::code_step(secret, "000000", 59).unwrap(), None);
+    }
+
+    #[test]
+    fn test_otpauth_url() {
+        assert_eq!(
+            super::otpauth_url("example.com", "a b+c@example.com", "ABC"),
+            "otpauth://totp/example.com:a%20b%2Bc%40example.com?secret=ABC&issuer=example.com&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
@@ -28,3 +28,46 @@ pub(crate) fn user_data_from_email_or_username<S: AsRef<str>>(
         )
     }
 }
+
+/// Id of the logged in user and their session id. Errors if the request is not made by a logged
+/// in user, or if their session has expired.
+pub(crate) fn logged_in_user(
+    conn: &mut ft_sdk::Connection,
+    sid: Option<String>,
+    config: &crate::Config,
+) -> Result<(ft_sdk::UserId, String), ft_sdk::Error> {
+    let sid = sid.ok_or_else(|| ft_sdk::unauthorised!("You must be logged in."))?;
+
+    match common::session::ud(conn, Some(sid.clone()), &config.session)? {
+        Some(ud) => Ok((ft_sdk::UserId(ud.id), sid)),
+        None => Err(ft_sdk::unauthorised!("You must be logged in.").into()),
+    }
+}
+
+/// Rate limit `action`, an endpoint that sends mails to `email`, so it can not be used to flood an
+/// inbox. Requests are limited by the IP address of the client too.
+pub(crate) fn rate_limit_mail(
+    conn: &mut ft_sdk::Connection,
+    action: &str,
+    email: &str,
+    client: &common::session::ClientInfo,
+    field: &str,
+) -> Result<(), ft_sdk::Error> {
+    if let Some(ip) = client.ip.as_deref() {
+        common::rate_limit::check(
+            conn,
+            action,
+            &format!("ip:{ip}"),
+            &email_auth::IP_RATE_LIMIT,
+            field,
+        )?;
+    }
+
+    common::rate_limit::check(
+        conn,
+        action,
+        &format!("email:{}", email.to_lowercase()),
+        &email_auth::EMAIL_RATE_LIMIT,
+        field,
+    )
+}
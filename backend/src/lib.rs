@@ -6,16 +6,49 @@
 extern crate self as email_auth;
 
 mod handlers;
+mod password_hash;
+mod password_policy;
 mod urls;
 
 pub(crate) use handlers::utils;
 
 pub const PROVIDER_ID: &str = "email";
 pub const SUBSCRIPTION_PROVIDER_ID: &str = "subscription";
+pub const GITHUB_PROVIDER_ID: &str = "github";
 pub const EMAIL_CONF_CODE_KEY: &str = "email_confirmation_code";
 pub const PASSWORD_RESET_CODE_KEY: &str = "password_reset_code";
 pub const PASSWORD_RESET_CODE_SENT_AT: &str = "password_reset_code_sent_at";
 pub const EMAIL_CONF_SENT_AT: &str = "email_conf_sent_at";
+pub const MAGIC_LINK_CODE_KEY: &str = "magic_link_code";
+pub const MAGIC_LINK_CODE_SENT_AT: &str = "magic_link_code_sent_at";
+/// argon2 hash of the 6 digit code sent by `login_code::request_login_code`
+pub const LOGIN_CODE_KEY: &str = "login_code";
+pub const LOGIN_CODE_SENT_AT: &str = "login_code_sent_at";
+/// wrong codes submitted for the current `LOGIN_CODE_KEY`
+pub const LOGIN_CODE_ATTEMPTS: &str = "login_code_attempts";
+pub const ACCOUNT_UNLOCK_CODE_KEY: &str = "account_unlock_code";
+pub const ACCOUNT_UNLOCK_CODE_SENT_AT: &str = "account_unlock_code_sent_at";
+/// encrypted TOTP secret, present only if the user has two-factor authentication enabled
+pub const TOTP_SECRET_KEY: &str = common::login::TOTP_SECRET_KEY;
+/// encrypted TOTP secret made by `start_two_factor_setup` but not confirmed by the user yet
+pub const TOTP_PENDING_SECRET_KEY: &str = "totp_pending_secret";
+/// the 30 second step of the last TOTP code accepted, codes of this step or earlier are refused
+pub const TOTP_LAST_STEP_KEY: &str = "totp_last_step";
+/// argon2 hashes of the unused recovery codes of a user with two-factor authentication
+pub const TOTP_RECOVERY_CODES_KEY: &str = "totp_recovery_codes";
+/// encrypted recovery codes made with `TOTP_PENDING_SECRET_KEY`, they are hashed into
+/// `TOTP_RECOVERY_CODES_KEY` once the setup is confirmed
+pub const TOTP_PENDING_RECOVERY_CODES_KEY: &str = "totp_pending_recovery_codes";
+/// Emails sent to the same address by an endpoint that sends mails
+pub const EMAIL_RATE_LIMIT: common::rate_limit::Limit = common::rate_limit::Limit {
+    max: 3,
+    window_seconds: 60 * 60,
+};
+/// Requests from the same IP address to an endpoint that sends mails
+pub const IP_RATE_LIMIT: common::rate_limit::Limit = common::rate_limit::Limit {
+    max: 20,
+    window_seconds: 60 * 60,
+};
 // TODO: make this configurable as well. We need DKIM support among other things before we can do
 // this
 pub const EMAIL_SENDER: &str = "support@fifthtry.com";
@@ -25,6 +58,19 @@ pub const EMAIL_SENDER: &str = "support@fifthtry.com";
 pub struct Config {
     email_sender_name: String,
     email_reply_to: String,
+    /// The user that can use admin only handlers like `import_users`
+    #[serde(default)]
+    pub(crate) super_user_id: Option<i64>,
+    #[serde(flatten)]
+    pub(crate) session: common::session::Config,
+    #[serde(flatten)]
+    pub(crate) github: handlers::github::Config,
+    #[serde(flatten)]
+    pub(crate) link: common::link::Config,
+    #[serde(flatten)]
+    pub(crate) password_policy: password_policy::Config,
+    #[serde(flatten)]
+    pub(crate) password_hash: password_hash::Config,
 }
 
 impl Config {
@@ -0,0 +1,203 @@
+//! Parameters passwords are hashed with. Hashes store the parameters they were made with, so the
+//! cost can be raised any time: old hashes keep working and are upgraded on the next login, see
+//! `needs_rehash`. The same goes for hashes imported from other systems, see `verify_legacy`.
+
+/// Part of the lets-auth config (see `config.ftd`), the defaults are those of the argon2 crate
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// `argon2id`, `argon2i` or `argon2d`
+    #[serde(default = "default_algorithm")]
+    pub password_hash_algorithm: String,
+    /// Memory cost in KiB
+    #[serde(default = "default_memory")]
+    pub password_hash_memory: u32,
+    #[serde(default = "default_iterations")]
+    pub password_hash_iterations: u32,
+    #[serde(default = "default_parallelism")]
+    pub password_hash_parallelism: u32,
+}
+
+fn default_algorithm() -> String {
+    argon2::Algorithm::default().as_str().to_string()
+}
+
+fn default_memory() -> u32 {
+    argon2::Params::DEFAULT_M_COST
+}
+
+fn default_iterations() -> u32 {
+    argon2::Params::DEFAULT_T_COST
+}
+
+fn default_parallelism() -> u32 {
+    argon2::Params::DEFAULT_P_COST
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            password_hash_algorithm: default_algorithm(),
+            password_hash_memory: default_memory(),
+            password_hash_iterations: default_iterations(),
+            password_hash_parallelism: default_parallelism(),
+        }
+    }
+}
+
+impl Config {
+    /// The hasher new hashes are made with
+    pub fn argon2(&self) -> Result<argon2::Argon2<'static>, ft_sdk::Error> {
+        let algorithm: argon2::Algorithm = self
+            .password_hash_algorithm
+            .parse()
+            .map_err(|e| ft_sdk::server_error!("invalid password-hash-algorithm: {e:?}"))?;
+
+        let params = argon2::Params::new(
+            self.password_hash_memory,
+            self.password_hash_iterations,
+            self.password_hash_parallelism,
+            None,
+        )
+        .map_err(|e| ft_sdk::server_error!("invalid password hash parameters: {e:?}"))?;
+
+        Ok(argon2::Argon2::new(
+            algorithm,
+            argon2::Version::default(),
+            params,
+        ))
+    }
+}
+
+/// `hash` was not made with the current `config`, or is not an argon2 hash at all
+pub fn needs_rehash(hash: &str, config: &Config) -> bool {
+    let Ok(parsed) = argon2::PasswordHash::new(hash) else {
+        return true;
+    };
+
+    let Ok(params) = argon2::Params::try_from(&parsed) else {
+        return true;
+    };
+
+    parsed.algorithm.as_str() != config.password_hash_algorithm
+        || parsed.version != Some(argon2::Version::default().into())
+        || params.m_cost() != config.password_hash_memory
+        || params.t_cost() != config.password_hash_iterations
+        || params.p_cost() != config.password_hash_parallelism
+}
+
+/// Check `password` against a hash imported from another system. Supported formats:
+///
+/// - bcrypt: `$2a$`, `$2b$` and `$2y$`
+/// - Django PBKDF2: `pbkdf2_sha256$<iterations>$<salt>$<base64 hash>`
+/// - Django scrypt: `scrypt$<N>$<salt>$<r>$<p>$<base64 hash>`
+///
+/// Hashes in any other format match no password. These hashes are replaced by an argon2 hash on
+/// the first successful login, see `needs_rehash`.
+pub fn verify_legacy(hash: &str, password: &str) -> Result<bool, ft_sdk::Error> {
+    use base64::Engine;
+
+    if ["$2a$", "$2b$", "$2y$"].iter().any(|p| hash.starts_with(p)) {
+        return bcrypt::verify(password, hash)
+            .map_err(|e| ft_sdk::server_error!("error verifying bcrypt hash: {e:?}").into());
+    }
+
+    let parts: Vec<&str> = hash.split('$').collect();
+
+    let (expected, computed) = match parts.as_slice() {
+        ["pbkdf2_sha256", iterations, salt, expected] => {
+            let iterations: u32 = iterations
+                .parse()
+                .map_err(|_| ft_sdk::server_error!("invalid pbkdf2 iterations: {iterations}"))?;
+            let expected = base64::engine::general_purpose::STANDARD.decode(expected)?;
+
+            let mut computed = vec![0u8; expected.len()];
+            pbkdf2::pbkdf2_hmac::<sha2::Sha256>(
+                password.as_bytes(),
+                salt.as_bytes(),
+                iterations,
+                &mut computed,
+            );
+
+            (expected, computed)
+        }
+        ["scrypt", n, salt, r, p, expected] => {
+            let n: u64 = n
+                .parse()
+                .map_err(|_| ft_sdk::server_error!("invalid scrypt N: {n}"))?;
+            if !n.is_power_of_two() {
+                return Err(ft_sdk::server_error!("invalid scrypt N: {n}").into());
+            }
+            let r: u32 = r
+                .parse()
+                .map_err(|_| ft_sdk::server_error!("invalid scrypt r: {r}"))?;
+            let p: u32 = p
+                .parse()
+                .map_err(|_| ft_sdk::server_error!("invalid scrypt p: {p}"))?;
+            let expected = base64::engine::general_purpose::STANDARD.decode(expected)?;
+
+            let params = scrypt::Params::new(n.trailing_zeros() as u8, r, p, expected.len())
+                .map_err(|e| ft_sdk::server_error!("invalid scrypt parameters: {e:?}"))?;
+
+            let mut computed = vec![0u8; expected.len()];
+            scrypt::scrypt(password.as_bytes(), salt.as_bytes(), &params, &mut computed)
+                .map_err(|e| ft_sdk::server_error!("error computing scrypt hash: {e:?}"))?;
+
+            (expected, computed)
+        }
+        // a hash we can not read matches no password, like a wrong password it must not tell
+        // anything more to whoever is trying
+        _ => return Ok(false),
+    };
+
+    Ok(constant_time_eq(&expected, &computed))
+}
+
+/// Compare without returning early, so the time taken does not tell how much of the hash matched
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_needs_rehash() {
+        let config = super::Config::default();
+
+        // argon2 crate defaults
+        let current = "$argon2id$v=19$m=19456,t=2,p=1$c29tZXNhbHQ$3GEEKy/0fFQ5UzjwGmPD1Q4ydHGTgxiB1bXrhk0pYm8";
+        let weaker =
+            "$argon2id$v=19$m=4096,t=3,p=1$c29tZXNhbHQ$3GEEKy/0fFQ5UzjwGmPD1Q4ydHGTgxiB1bXrhk0pYm8";
+        let argon2i =
+            "$argon2i$v=19$m=19456,t=2,p=1$c29tZXNhbHQ$3GEEKy/0fFQ5UzjwGmPD1Q4ydHGTgxiB1bXrhk0pYm8";
+
+        assert!(!super::needs_rehash(current, &config));
+        assert!(super::needs_rehash(weaker, &config));
+        assert!(super::needs_rehash(argon2i, &config));
+        assert!(super::needs_rehash("not a hash", &config));
+
+        let stronger = super::Config {
+            password_hash_memory: 65536,
+            ..super::Config::default()
+        };
+        assert!(super::needs_rehash(current, &stronger));
+        assert!(super::needs_rehash(
+            "pbkdf2_sha256$1000$seasalt$mQnueSakb748zqBAC1tmWVZsZbi2zPGZarEzTGdfmso=",
+            &config
+        ));
+    }
+
+    #[test]
+    fn test_verify_legacy() {
+        let pbkdf2 = "pbkdf2_sha256$1000$seasalt$mQnueSakb748zqBAC1tmWVZsZbi2zPGZarEzTGdfmso=";
+        assert!(super::verify_legacy(pbkdf2, "correct horse").unwrap());
+        assert!(!super::verify_legacy(pbkdf2, "correct horse!").unwrap());
+
+        let scrypt = "scrypt$1024$seasalt$8$1$b9vqOe0B5HZh2f10Q9RPN9OFNPtmb+ezp5YOlnZsJQ0vQ5I3eq8Zt7GFMuqQ58xCxTIvVL1QCL/PTXEWr+RNHA==";
+        assert!(super::verify_legacy(scrypt, "correct horse").unwrap());
+        assert!(!super::verify_legacy(scrypt, "Correct horse").unwrap());
+
+        assert!(!super::verify_legacy("md5$seasalt$abc", "correct horse").unwrap());
+        assert!(!super::verify_legacy("", "").unwrap());
+    }
+}
@@ -0,0 +1,221 @@
+//! What makes a password acceptable, checked by `create_account` and `set_password`.
+
+/// The most common passwords from public breach dumps, one per line. Matched case-insensitively.
+///
+/// Most of them are shorter than the default `password_min_length` and never get here with it.
+/// The list is kept whole anyway, the minimum length is configurable and a site that lowers it
+/// still needs them rejected.
+const COMMON_PASSWORDS: &str = include_str!("common-passwords.txt");
+
+/// Password rules, part of the lets-auth config (see `config.ftd`)
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    #[serde(default = "default_min_length")]
+    pub password_min_length: usize,
+    #[serde(default)]
+    pub password_require_lowercase: bool,
+    #[serde(default)]
+    pub password_require_uppercase: bool,
+    #[serde(default)]
+    pub password_require_digit: bool,
+    #[serde(default)]
+    pub password_require_symbol: bool,
+    /// 0 to 4, see `strength`
+    #[serde(default = "default_min_strength")]
+    pub password_min_strength: u8,
+}
+
+fn default_min_length() -> usize {
+    8
+}
+
+fn default_min_strength() -> u8 {
+    2
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            password_min_length: default_min_length(),
+            password_require_lowercase: false,
+            password_require_uppercase: false,
+            password_require_digit: false,
+            password_require_symbol: false,
+            password_min_strength: default_min_strength(),
+        }
+    }
+}
+
+/// The first rule of `config` that `password` breaks, as a message for the user
+pub fn check(password: &str, config: &Config) -> Option<String> {
+    if password.chars().count() < config.password_min_length {
+        return Some(format!(
+            "Password must be at least {} characters long.",
+            config.password_min_length
+        ));
+    }
+
+    let classes = [
+        (
+            config.password_require_lowercase,
+            password.chars().any(|c| c.is_lowercase()),
+            "Password must contain a lowercase letter.",
+        ),
+        (
+            config.password_require_uppercase,
+            password.chars().any(|c| c.is_uppercase()),
+            "Password must contain an uppercase letter.",
+        ),
+        (
+            config.password_require_digit,
+            password.chars().any(|c| c.is_ascii_digit()),
+            "Password must contain a digit.",
+        ),
+        (
+            config.password_require_symbol,
+            password.chars().any(|c| !c.is_alphanumeric()),
+            "Password must contain a symbol.",
+        ),
+    ];
+
+    if let Some((_, _, message)) = classes
+        .iter()
+        .find(|(required, present, _)| *required && !present)
+    {
+        return Some(message.to_string());
+    }
+
+    if is_common(password) {
+        return Some(
+            "This password is too common, it has appeared in data breaches. Please choose another."
+                .to_string(),
+        );
+    }
+
+    if strength(password) < config.password_min_strength {
+        return Some(
+            "Password is too easy to guess. Avoid repeated characters and sequences like abc or \
+             123, or use a longer password."
+                .to_string(),
+        );
+    }
+
+    None
+}
+
+fn is_common(password: &str) -> bool {
+    COMMON_PASSWORDS
+        .lines()
+        .any(|common| common.eq_ignore_ascii_case(password))
+}
+
+/// How hard `password` is to guess, from 0 (trivial) to 4 (very strong), a rough brute force
+/// estimate with thresholds at 10^3, 10^6, 10^8 and 10^10 guesses. It is not zxcvbn: there is no
+/// pattern matching beyond what is described below, so the same level is easier to reach here.
+///
+/// The estimate is the size of the character pool used raised to the length of the password,
+/// where characters that repeat the previous one or continue a sequence (`aaa`, `abc`, `321`)
+/// count for a quarter of a character. Dictionary words are left to `is_common`.
+pub fn strength(password: &str) -> u8 {
+    let chars: Vec<char> = password.chars().collect();
+
+    let pool: u32 = [
+        (chars.iter().any(|c| c.is_ascii_lowercase()), 26),
+        (chars.iter().any(|c| c.is_ascii_uppercase()), 26),
+        (chars.iter().any(|c| c.is_ascii_digit()), 10),
+        (
+            chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' '),
+            33,
+        ),
+        (chars.iter().any(|c| !c.is_ascii()), 100),
+    ]
+    .iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, size)| size)
+    .sum();
+
+    if pool == 0 {
+        return 0;
+    }
+
+    let length: f64 = chars
+        .iter()
+        .enumerate()
+        .map(|(i, c)| match i.checked_sub(1).map(|p| chars[p]) {
+            Some(prev) if (*c as i64 - prev as i64).abs() <= 1 => 0.25,
+            _ => 1.0,
+        })
+        .sum();
+
+    let bits = length * f64::from(pool).log2();
+
+    match bits {
+        b if b < 10.0 => 0,
+        b if b < 20.0 => 1,
+        b if b < 26.6 => 2,
+        b if b < 33.2 => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_strength() {
+        assert_eq!(super::strength(""), 0);
+        assert_eq!(super::strength("12345678"), 0);
+        assert_eq!(super::strength("aaaaaaaa"), 1);
+        assert_eq!(super::strength("abcdefgh"), 1);
+        assert_eq!(super::strength("kq7x"), 2);
+        assert_eq!(super::strength("correct horse battery staple"), 4);
+        assert_eq!(super::strength("Tr0ub4dor&3"), 4);
+    }
+
+    #[test]
+    fn test_check() {
+        let config = super::Config::default();
+
+        assert_eq!(
+            super::check("short", &config),
+            Some("Password must be at least 8 characters long.".to_string())
+        );
+        assert_eq!(
+            super::check("Password1", &config),
+            Some(
+                "This password is too common, it has appeared in data breaches. Please choose \
+                 another."
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            super::check("abababab", &config),
+            Some(
+                "Password is too easy to guess. Avoid repeated characters and sequences like abc \
+                 or 123, or use a longer password."
+                    .to_string()
+            )
+        );
+        assert_eq!(super::check("correct horse battery staple", &config), None);
+
+        let strict = super::Config {
+            password_require_uppercase: true,
+            password_require_digit: true,
+            password_require_symbol: true,
+            ..super::Config::default()
+        };
+
+        assert_eq!(
+            super::check("correct horse battery staple", &strict),
+            Some("Password must contain an uppercase letter.".to_string())
+        );
+        assert_eq!(
+            super::check("Correct horse battery staple", &strict),
+            Some("Password must contain a digit.".to_string())
+        );
+        assert_eq!(
+            super::check("Correct horse battery staple 4", &strict),
+            None
+        );
+    }
+}
@@ -21,8 +21,8 @@ impl From<&str> for Route {
     fn from(s: &str) -> Self {
         match s {
             "/login/" => Self::Login,
-            "/github/" => Self::GithubLogin,
-            "/github/callback/" => Self::GithubCallback,
+            "/github-login/" => Self::GithubLogin,
+            "/github-callback/" => Self::GithubCallback,
             "/logout/" => Self::Logout,
             "/create-account/" => Self::CreateAccount,
             "/email-confirmation-sent/" => Self::EmailConfirmationSent,
@@ -42,8 +42,8 @@ impl std::fmt::Display for Route {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Login => write!(f, "/login/"),
-            Self::GithubLogin => write!(f, "/github/"),
-            Self::GithubCallback => write!(f, "/github/callback/"),
+            Self::GithubLogin => write!(f, "/github-login/"),
+            Self::GithubCallback => write!(f, "/github-callback/"),
             Self::Logout => write!(f, "/logout/"),
             Self::CreateAccount => write!(f, "/create-account/"),
             Self::EmailConfirmationSent => write!(f, "/email-confirmation-sent/"),
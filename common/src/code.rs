@@ -0,0 +1,43 @@
+//! Short codes users type in to prove they got a message, the sign-in codes of `login_code` in
+//! the backend and the sms codes of `otp` in the mobile provider.
+
+/// 4_294_000_000 is the largest multiple of 1_000_000 that fits in a u32, values above it are
+/// dropped so every code is equally likely
+const LIMIT: u32 = 4_294_000_000;
+
+/// A random 6 digit code, zero padded
+pub fn generate() -> String {
+    use rand_core::RngCore;
+
+    from_random(|| ft_sdk::Rng {}.next_u32())
+}
+
+/// The code made from the first value `next_u32` returns that is below `LIMIT`
+fn from_random(mut next_u32: impl FnMut() -> u32) -> String {
+    let n = loop {
+        let n = next_u32();
+        if n < LIMIT {
+            break n;
+        }
+    };
+
+    format!("{:06}", n % 1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    fn code(values: &[u32]) -> String {
+        let mut values = values.iter();
+        super::from_random(|| *values.next().expect("ran out of values"))
+    }
+
+    #[test]
+    fn test_from_random() {
+        assert_eq!(code(&[123_456_789]), "456789");
+        assert_eq!(code(&[42]), "000042");
+        assert_eq!(code(&[0]), "000000");
+        assert_eq!(code(&[super::LIMIT - 1]), "999999");
+        // values that would make low codes more likely are skipped
+        assert_eq!(code(&[u32::MAX, super::LIMIT, 7]), "000007");
+    }
+}
@@ -0,0 +1,47 @@
+/// Send `request` with `ft_sdk::http::send` and parse the JSON response body. Non 2xx responses
+/// are errors.
+pub fn json<T: serde::de::DeserializeOwned>(
+    request: http::Request<bytes::Bytes>,
+) -> Result<T, ft_sdk::Error> {
+    json_with(request, ft_sdk::http::send)
+}
+
+/// Same as `json`, but the request is sent with `send`. Tests use this to stand in for the server.
+pub fn json_with<T: serde::de::DeserializeOwned, E: std::fmt::Debug>(
+    request: http::Request<bytes::Bytes>,
+    send: impl FnOnce(http::Request<bytes::Bytes>) -> Result<http::Response<bytes::Bytes>, E>,
+) -> Result<T, ft_sdk::Error> {
+    let uri = request.uri().to_string();
+
+    let response =
+        send(request).map_err(|e| ft_sdk::server_error!("request to {uri} failed: {e:?}"))?;
+
+    if !response.status().is_success() {
+        ft_sdk::println!("{uri} returned {}", response.status());
+        return Err(ft_sdk::server_error!("request to {uri} failed: {}", response.status()).into());
+    }
+
+    Ok(serde_json::from_slice(response.body())?)
+}
+
+/// Percent encode `s` for use in a query string or an `application/x-www-form-urlencoded` body
+pub fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// `application/x-www-form-urlencoded` body made of `params`
+pub fn form_body(params: &[(&str, &str)]) -> bytes::Bytes {
+    params
+        .iter()
+        .map(|(k, v)| format!("{}={}", url_encode(k), url_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+        .into()
+}
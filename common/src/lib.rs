@@ -1,3 +1,10 @@
+pub mod code;
+pub mod fetch;
+pub mod link;
+pub mod login;
+pub mod rate_limit;
+pub mod session;
+
 pub fn validate_identity(
     field: &str,
     identity: &str,
@@ -18,12 +25,34 @@ pub fn validate_identity(
     Ok(())
 }
 
-pub fn session_cookie(sid: &str, host: ft_sdk::Host) -> Result<http::HeaderValue, ft_sdk::Error> {
+pub fn session_cookie(
+    sid: &str,
+    host: ft_sdk::Host,
+    config: &session::Config,
+) -> Result<http::HeaderValue, ft_sdk::Error> {
+    build_session_cookie(
+        sid,
+        host,
+        cookie::time::Duration::seconds(config.session_max_age),
+    )
+}
+
+/// Same cookie as `session_cookie` but with max-age 0, the browser drops it right away. Used on
+/// logout.
+pub fn expired_session_cookie(host: ft_sdk::Host) -> Result<http::HeaderValue, ft_sdk::Error> {
+    build_session_cookie("", host, cookie::time::Duration::ZERO)
+}
+
+fn build_session_cookie(
+    sid: &str,
+    host: ft_sdk::Host,
+    max_age: cookie::time::Duration,
+) -> Result<http::HeaderValue, ft_sdk::Error> {
     // DO NOT CHANGE THINGS HERE, consult logout code in fastn.
     let cookie = cookie::Cookie::build((ft_sdk::auth::SESSION_KEY, sid))
         .domain(host.without_port())
         .path("/")
-        .max_age(cookie::time::Duration::seconds(34560000))
+        .max_age(max_age)
         .same_site(cookie::SameSite::Strict)
         .build();
 
@@ -0,0 +1,174 @@
+//! Linking identities from different providers to one `fastn_user`.
+//!
+//! Each provider stores its data under its own key of `fastn_user.data`, e.g.
+//! `data -> 'github'`, so a user with more than one key there can log in with any of those
+//! providers.
+
+/// Account linking policy, part of the lets-auth config (see `config.ftd`).
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// When someone logs in with a provider for the first time, and a verified email of theirs
+    /// is a verified email of exactly one existing user, log them in as that user instead of
+    /// creating a new one. Only turn this on if every enabled provider verifies emails properly.
+    #[serde(default)]
+    pub auto_link_verified_emails: bool,
+}
+
+/// Find the user `data` from `provider_id` logs in as, create one if needed, and store `data`
+/// under `provider_id` for that user.
+///
+/// - `existing` is the user this identity is already linked to, if any.
+/// - `logged_in` is the user logged in on the current session, if any. The identity is linked to
+///   them, this is how a logged-in user attaches another provider to their account.
+///
+/// An identity already linked to someone else is never moved to the logged-in user, and a user
+/// that already has another identity of `provider_id` does not get this one: that would overwrite
+/// the other identity, and take away the way its owner logs in.
+pub fn resolve(
+    conn: &mut ft_sdk::Connection,
+    provider_id: &str,
+    data: ft_sdk::auth::ProviderData,
+    existing: Option<ft_sdk::UserId>,
+    logged_in: Option<ft_sdk::UserId>,
+    config: &Config,
+) -> Result<ft_sdk::UserId, ft_sdk::Error> {
+    let is_linked = existing.is_some();
+
+    let user_id = match (existing, logged_in) {
+        (Some(existing), Some(logged_in)) if existing.0 != logged_in.0 => {
+            return Err(ft_sdk::single_error(
+                "provider",
+                "This account is already linked to another user.",
+            )
+            .into());
+        }
+        (Some(user_id), _) | (None, Some(user_id)) => user_id,
+        (None, None) => {
+            let linked = if config.auto_link_verified_emails {
+                user_by_verified_emails(conn, provider_id, &data.verified_emails)?
+            } else {
+                None
+            };
+
+            match linked {
+                Some(user_id) => {
+                    ft_sdk::println!("auto linking {provider_id} to user {}", user_id.0);
+                    user_id
+                }
+                None => {
+                    return Ok(ft_sdk::auth::provider::create_user(
+                        conn,
+                        provider_id,
+                        data,
+                    )?);
+                }
+            }
+        }
+    };
+
+    if !is_linked && providers(conn, &user_id)?.iter().any(|p| p == provider_id) {
+        ft_sdk::println!(
+            "user {} already has another {provider_id} identity",
+            user_id.0
+        );
+        return Err(ft_sdk::single_error(
+            "provider",
+            "Your account is already linked to another account of this provider. Unlink it first.",
+        )
+        .into());
+    }
+
+    // this also refreshes the data of an already linked identity, profile and emails may have
+    // changed at the provider since the last login
+    ft_sdk::auth::provider::update_user(conn, provider_id, &user_id, data, false)?;
+
+    Ok(user_id)
+}
+
+/// The one user, other than those linked through `provider_id`, that has any of `emails` as a
+/// verified email with any provider. `None` if no user or more than one user matches.
+fn user_by_verified_emails(
+    conn: &mut ft_sdk::Connection,
+    provider_id: &str,
+    emails: &[String],
+) -> Result<Option<ft_sdk::UserId>, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct User {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        id: i64,
+    }
+
+    let mut ids = std::collections::BTreeSet::new();
+
+    for email in emails {
+        let users = diesel::sql_query(
+            r#"
+            SELECT DISTINCT fastn_user.id AS id
+            FROM fastn_user, json_each(fastn_user.data) AS provider
+            WHERE
+                provider.key != $1
+                AND EXISTS (
+                    SELECT 1
+                    FROM json_each(provider.value -> 'verified_emails')
+                    WHERE value = $2
+                )
+            "#,
+        )
+        .bind::<diesel::sql_types::Text, _>(provider_id)
+        .bind::<diesel::sql_types::Text, _>(email)
+        .get_results::<User>(conn)?;
+
+        ids.extend(users.into_iter().map(|u| u.id));
+    }
+
+    if ids.len() == 1 {
+        Ok(ids.into_iter().next().map(ft_sdk::UserId))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Providers that have data for `user_id`, i.e. the keys of `fastn_user.data`.
+pub fn providers(
+    conn: &mut ft_sdk::Connection,
+    user_id: &ft_sdk::UserId,
+) -> Result<Vec<String>, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Provider {
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        key: String,
+    }
+
+    Ok(diesel::sql_query(
+        "SELECT provider.key AS key FROM fastn_user, json_each(fastn_user.data) AS provider WHERE \
+         fastn_user.id = $1",
+    )
+    .bind::<diesel::sql_types::BigInt, _>(user_id.0)
+    .get_results::<Provider>(conn)?
+    .into_iter()
+    .map(|p| p.key)
+    .collect())
+}
+
+/// Remove the data of `provider_id` from `user_id`, they can no longer log in with it. The caller
+/// has to make sure this is not the last way the user can log in, and that it does not remove the
+/// two-factor settings of the user, see `login::TWO_FACTOR_PROVIDER_ID`.
+pub fn unlink(
+    conn: &mut ft_sdk::Connection,
+    user_id: &ft_sdk::UserId,
+    provider_id: &str,
+) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    diesel::sql_query("UPDATE fastn_user SET data = json_remove(data, $1) WHERE id = $2")
+        .bind::<diesel::sql_types::Text, _>(format!("$.\"{provider_id}\""))
+        .bind::<diesel::sql_types::BigInt, _>(user_id.0)
+        .execute(conn)?;
+
+    Ok(())
+}
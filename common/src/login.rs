@@ -0,0 +1,132 @@
+//! Finishing a login. Two-factor authentication is set up with the `email` provider of the
+//! backend, but it protects the user whichever provider they log in with, so every provider logs
+//! users in through `complete_login`.
+
+/// Key in `fastn_session.data` that holds a login waiting for the second factor
+pub const PENDING_LOGIN_KEY: &str = "pending-2fa";
+/// Provider the two-factor settings of a user are stored under
+pub const TWO_FACTOR_PROVIDER_ID: &str = "email";
+/// Custom data key of the `email` provider with the encrypted TOTP secret, present only if the
+/// user has two-factor authentication enabled
+pub const TOTP_SECRET_KEY: &str = "totp_secret";
+
+/// State of a login that has passed the first factor but not the second one yet, see
+/// `two_factor::verify_two_factor` in the backend.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PendingLogin {
+    pub uid: i64,
+    pub next: String,
+    pub started_at: i64,
+}
+
+/// true if the user with this `email` provider data has finished TOTP enrolment
+pub fn two_factor_enabled(email_data: Option<&ft_sdk::auth::ProviderData>) -> bool {
+    email_data.is_some_and(|data| data.get_custom::<String>(TOTP_SECRET_KEY).is_some())
+}
+
+/// true if `user_id` has two-factor authentication enabled. Users that never had the `email`
+/// provider can not have it.
+pub fn user_two_factor_enabled(
+    conn: &mut ft_sdk::Connection,
+    user_id: &ft_sdk::UserId,
+) -> Result<bool, ft_sdk::Error> {
+    match ft_sdk::auth::provider::user_data_by_id(conn, TWO_FACTOR_PROVIDER_ID, user_id) {
+        Ok(data) => Ok(two_factor_enabled(Some(&data))),
+        Err(ft_sdk::auth::UserDataError::NoDataFound) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Log in `user_id` after they have proven who they are, and return where to send them along with
+/// the session cookie. Users with `two_factor` enabled are sent to the /two-factor/ page instead,
+/// they are logged in by `two_factor::verify_two_factor` of the backend once they enter their
+/// code.
+#[expect(clippy::too_many_arguments)]
+pub fn complete_login(
+    conn: &mut ft_sdk::Connection,
+    user_id: &ft_sdk::UserId,
+    two_factor: bool,
+    sid: Option<String>,
+    next: String,
+    host: ft_sdk::Host,
+    client: &crate::session::ClientInfo,
+    app_url: &ft_sdk::AppUrl,
+    config: &crate::session::Config,
+) -> Result<(String, http::HeaderValue), ft_sdk::Error> {
+    let sid = crate::session::reusable(conn, sid, config)?;
+
+    if two_factor {
+        // the session stays logged out till the second factor is verified
+        let sid = match sid {
+            Some(ft_sdk::SessionID(sid)) => sid,
+            None => crate::session::create_anonymous(conn, config)?,
+        };
+
+        start_pending_login(conn, &sid, user_id, next)?;
+
+        let two_factor_url = app_url.join("/two-factor/").inspect_err(|e| {
+            ft_sdk::println!("failed to join url: {:?}", e);
+        })?;
+
+        let cookie = crate::session_cookie(sid.as_str(), host, config)?;
+        return Ok((two_factor_url, cookie));
+    }
+
+    let ft_sdk::SessionID(sid) = ft_sdk::auth::provider::login(conn, user_id, sid)?;
+    crate::session::on_login(conn, &sid, client, config)?;
+
+    let cookie = crate::session_cookie(sid.as_str(), host, config)?;
+    Ok((next, cookie))
+}
+
+/// Put the session `sid` in the pending-2FA state for `user_id`. The user is logged in by
+/// `two_factor::verify_two_factor` of the backend, after which they are sent to `next`.
+fn start_pending_login(
+    conn: &mut ft_sdk::Connection,
+    sid: &str,
+    user_id: &ft_sdk::UserId,
+    next: String,
+) -> Result<(), ft_sdk::Error> {
+    let pending = PendingLogin {
+        uid: user_id.0,
+        next,
+        started_at: ft_sdk::env::now()
+            .timestamp_nanos_opt()
+            .expect("unexpected out of range datetime"),
+    };
+
+    crate::session::set_data(conn, sid, PENDING_LOGIN_KEY, &pending)
+}
+
+#[cfg(test)]
+mod tests {
+    fn data(custom: serde_json::Value) -> ft_sdk::auth::ProviderData {
+        ft_sdk::auth::ProviderData {
+            identity: "jane".to_string(),
+            username: Some("jane".to_string()),
+            name: None,
+            emails: vec!["jane@example.com".to_string()],
+            verified_emails: vec!["jane@example.com".to_string()],
+            profile_picture: None,
+            custom,
+        }
+    }
+
+    #[test]
+    fn test_two_factor_enabled() {
+        // a user that only ever logged in with another provider
+        assert!(!super::two_factor_enabled(None));
+        assert!(!super::two_factor_enabled(Some(&data(
+            serde_json::json!({"login_code": "hash"})
+        ))));
+        // enrolment started but not confirmed
+        assert!(!super::two_factor_enabled(Some(&data(
+            serde_json::json!({"totp_pending_secret": "secret"})
+        ))));
+        // a user with 2FA who links GitHub or an OIDC provider still needs the second factor
+        assert!(super::two_factor_enabled(Some(&data(
+            serde_json::json!({super::TOTP_SECRET_KEY: "secret"})
+        ))));
+    }
+}
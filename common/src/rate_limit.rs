@@ -0,0 +1,103 @@
+/// At most `max` hits in any `window_seconds` long window.
+#[derive(Debug, Clone, Copy)]
+pub struct Limit {
+    pub max: i64,
+    pub window_seconds: i64,
+}
+
+/// Record a hit of `action` (e.g. `forgot-password`) by `key` (e.g. `email:foo@bar.com` or
+/// `ip:127.0.0.1`). If `key` already made `limit.max` hits in the last `limit.window_seconds`, the
+/// hit is not recorded and an error is returned against `field`.
+///
+/// Every hit is stored in `fastn_rate_limit`, so the window slides with the request, there is no
+/// burst allowed at the edge of a fixed window.
+///
+/// The part of `key` before the first `:` is its kind, all keys of a kind must be checked with
+/// the same `limit` for an `action`: hits of that kind older than the window are deleted here,
+/// including those of keys that are never seen again.
+pub fn check(
+    conn: &mut ft_sdk::Connection,
+    action: &str,
+    key: &str,
+    limit: &Limit,
+    field: &str,
+) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Hit {
+        #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+        created_at: chrono::DateTime<chrono::Utc>,
+    }
+
+    let now = ft_sdk::env::now();
+    let kind = key.split_once(':').map_or(key, |(kind, _)| kind);
+
+    // hits older than the window are of no use anymore
+    diesel::sql_query(
+        "DELETE FROM fastn_rate_limit WHERE action = $1 AND key LIKE $2 AND created_at <= $3",
+    )
+    .bind::<diesel::sql_types::Text, _>(action)
+    .bind::<diesel::sql_types::Text, _>(format!("{kind}:%"))
+    .bind::<diesel::sql_types::Timestamptz, _>(
+        now - chrono::TimeDelta::seconds(limit.window_seconds),
+    )
+    .execute(conn)?;
+
+    let hits: Vec<_> =
+        diesel::sql_query("SELECT created_at FROM fastn_rate_limit WHERE action = $1 AND key = $2")
+            .bind::<diesel::sql_types::Text, _>(action)
+            .bind::<diesel::sql_types::Text, _>(key)
+            .get_results::<Hit>(conn)?
+            .into_iter()
+            .map(|h| h.created_at)
+            .collect();
+
+    if !allowed(&hits, now, limit) {
+        ft_sdk::println!("rate limit hit for {action} by {key}");
+        return Err(
+            ft_sdk::single_error(field, "Too many requests. Please try again later.").into(),
+        );
+    }
+
+    diesel::sql_query("INSERT INTO fastn_rate_limit (action, key, created_at) VALUES ($1, $2, $3)")
+        .bind::<diesel::sql_types::Text, _>(action)
+        .bind::<diesel::sql_types::Text, _>(key)
+        .bind::<diesel::sql_types::Timestamptz, _>(now)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Can another hit be made at `now`, given the earlier `hits` of the same key
+fn allowed(
+    hits: &[chrono::DateTime<chrono::Utc>],
+    now: chrono::DateTime<chrono::Utc>,
+    limit: &Limit,
+) -> bool {
+    let window_start = now - chrono::TimeDelta::seconds(limit.window_seconds);
+    let recent = hits.iter().filter(|hit| **hit > window_start).count();
+
+    (recent as i64) < limit.max
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_allowed() {
+        let at = |seconds| chrono::DateTime::from_timestamp(seconds, 0).unwrap();
+        let limit = super::Limit {
+            max: 3,
+            window_seconds: 60,
+        };
+
+        assert!(super::allowed(&[], at(0), &limit));
+        assert!(super::allowed(&[at(0), at(10)], at(20), &limit));
+        assert!(!super::allowed(&[at(0), at(10), at(20)], at(30), &limit));
+        // the window slides: the first hit drops out exactly `window_seconds` after it was made
+        assert!(!super::allowed(&[at(0), at(10), at(20)], at(59), &limit));
+        assert!(super::allowed(&[at(0), at(10), at(20)], at(60), &limit));
+        // no burst at the edge of a fixed window
+        assert!(!super::allowed(&[at(50), at(55), at(59)], at(61), &limit));
+    }
+}
@@ -0,0 +1,534 @@
+/// Session lifetimes, part of the lets-auth config (see `config.ftd`).
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// A session is never valid for longer than these many seconds after it was created. This is
+    /// also the max-age of the session cookie.
+    #[serde(default = "default_session_max_age")]
+    pub session_max_age: i64,
+    /// A session that has not been used for these many seconds expires. `None` means sessions
+    /// only expire because of `session_max_age`.
+    #[serde(default)]
+    pub session_idle_timeout: Option<i64>,
+}
+
+/// 400 days, the longest max-age browsers accept for a cookie
+fn default_session_max_age() -> i64 {
+    34560000
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            session_max_age: default_session_max_age(),
+            session_idle_timeout: None,
+        }
+    }
+}
+
+/// User agent and IP address of the client that created a session. This is stored under the
+/// `client` key of `fastn_session.data`, so users can tell their sessions apart.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl ft_sdk::FromRequest for ClientInfo {
+    fn from_request(req: &http::Request<serde_json::Value>) -> Result<Self, ft_sdk::Error> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let hops = ft_sdk::env::var("TRUSTED_PROXY_HOPS".to_string())
+            .map(|v| v.parse().expect("TRUSTED_PROXY_HOPS should be a number"))
+            .unwrap_or(1);
+
+        Ok(ClientInfo {
+            user_agent: header("user-agent"),
+            ip: client_ip(
+                header("x-forwarded-for").as_deref(),
+                header("x-real-ip").as_deref(),
+                hops,
+            ),
+        })
+    }
+}
+
+/// IP address of the client, from the `x-forwarded-for` header set by the proxies in front of
+/// us. Each proxy appends the address it got the request from, anything before that is sent by
+/// the client and can not be trusted: with `hops` proxies (the TRUSTED_PROXY_HOPS env variable,
+/// default 1) the client is the `hops`th entry from the end. `x-real-ip` is used if there is no
+/// `x-forwarded-for`.
+fn client_ip(forwarded_for: Option<&str>, real_ip: Option<&str>, hops: usize) -> Option<String> {
+    let Some(forwarded_for) = forwarded_for else {
+        return real_ip.map(str::to_string);
+    };
+
+    let entries: Vec<&str> = forwarded_for
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+
+    // fewer entries than proxies, the first one is the closest we have to the client
+    entries
+        .len()
+        .checked_sub(hops.max(1))
+        .and_then(|i| entries.get(i))
+        .or(entries.first())
+        .map(|v| v.to_string())
+        .or_else(|| real_ip.map(str::to_string))
+}
+
+/// A row of `fastn_session` as shown to the user who owns it.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ActiveSession {
+    /// `rowid` of the session. The session id is a secret, so it never leaves the server, this
+    /// handle is used to refer to the session instead.
+    pub handle: i64,
+    /// true if this is the session the request was made with
+    pub current: bool,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Store `client` in the `data` of the session `sid`.
+pub fn set_client(
+    conn: &mut ft_sdk::Connection,
+    sid: &str,
+    client: &ClientInfo,
+) -> Result<(), ft_sdk::Error> {
+    set_data(conn, sid, "client", client)
+}
+
+/// Create a session that is not attached to any user. This is used when we need to keep some
+/// state for a visitor before they are logged in, e.g., while a login waits for the second
+/// factor.
+pub fn create_anonymous(
+    conn: &mut ft_sdk::Connection,
+    config: &Config,
+) -> Result<String, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    let sid = ft_sdk::Rng::generate_key(64);
+    let now = ft_sdk::env::now();
+
+    diesel::sql_query(
+        r#"
+        INSERT INTO fastn_session (id, uid, data, created_at, updated_at, expires_at)
+        VALUES ($1, NULL, '{}', $2, $2, $3)
+        "#,
+    )
+    .bind::<diesel::sql_types::Text, _>(&sid)
+    .bind::<diesel::sql_types::Timestamptz, _>(now)
+    .bind::<diesel::sql_types::Timestamptz, _>(
+        now + chrono::TimeDelta::seconds(config.session_max_age),
+    )
+    .execute(conn)?;
+
+    Ok(sid)
+}
+
+/// Store `value` under `key` in the `data` of the session `sid`.
+pub fn set_data<T: serde::Serialize>(
+    conn: &mut ft_sdk::Connection,
+    sid: &str,
+    key: &str,
+    value: &T,
+) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    diesel::sql_query("UPDATE fastn_session SET data = json_set(data, $1, json($2)) WHERE id = $3")
+        .bind::<diesel::sql_types::Text, _>(json_path(key))
+        .bind::<diesel::sql_types::Text, _>(serde_json::to_string(value)?)
+        .bind::<diesel::sql_types::Text, _>(sid)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Value stored under `key` in the `data` of the session `sid`, if any.
+pub fn get_data<T: serde::de::DeserializeOwned>(
+    conn: &mut ft_sdk::Connection,
+    sid: &str,
+    key: &str,
+) -> Result<Option<T>, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Value {
+        #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+        value: Option<String>,
+    }
+
+    let value = match diesel::sql_query(
+        // `->` always returns json, unlike json_extract() which unwraps strings
+        "SELECT data -> $1 AS value FROM fastn_session WHERE id = $2",
+    )
+    .bind::<diesel::sql_types::Text, _>(json_path(key))
+    .bind::<diesel::sql_types::Text, _>(sid)
+    .get_result::<Value>(conn)
+    {
+        Ok(Value { value: Some(v) }) => v,
+        Ok(Value { value: None }) | Err(diesel::result::Error::NotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Some(serde_json::from_str(&value)?))
+}
+
+/// Remove `key` from the `data` of the session `sid`.
+pub fn remove_data(
+    conn: &mut ft_sdk::Connection,
+    sid: &str,
+    key: &str,
+) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    diesel::sql_query("UPDATE fastn_session SET data = json_remove(data, $1) WHERE id = $2")
+        .bind::<diesel::sql_types::Text, _>(json_path(key))
+        .bind::<diesel::sql_types::Text, _>(sid)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// sqlite json path for a top level key, keys are quoted as they can contain `-`
+fn json_path(key: &str) -> String {
+    format!("$.\"{key}\"")
+}
+
+/// Bookkeeping for a session that was just logged in with `ft_sdk::auth::provider::login`:
+/// remember the client and set `expires_at`.
+pub fn on_login(
+    conn: &mut ft_sdk::Connection,
+    sid: &str,
+    client: &ClientInfo,
+    config: &Config,
+) -> Result<(), ft_sdk::Error> {
+    set_client(conn, sid, client)?;
+    refresh(conn, sid, config)?;
+
+    Ok(())
+}
+
+/// All sessions of `uid` that have not expired yet, most recently used first. `current_sid` is
+/// the session of the request, it is marked with `current: true` in the output.
+pub fn list(
+    conn: &mut ft_sdk::Connection,
+    uid: &ft_sdk::UserId,
+    current_sid: &str,
+) -> Result<Vec<ActiveSession>, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Session {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        handle: i64,
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        id: String,
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        data: String,
+        #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+        created_at: chrono::DateTime<chrono::Utc>,
+        #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+        updated_at: chrono::DateTime<chrono::Utc>,
+        #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    let sessions = diesel::sql_query(
+        r#"
+        SELECT
+            rowid AS handle, id, data, created_at, updated_at, expires_at
+        FROM fastn_session
+        WHERE
+            uid = $1
+            AND (expires_at IS NULL OR expires_at > $2)
+        ORDER BY updated_at DESC
+        "#,
+    )
+    .bind::<diesel::sql_types::BigInt, _>(uid.0)
+    .bind::<diesel::sql_types::Timestamptz, _>(ft_sdk::env::now())
+    .get_results::<Session>(conn)?;
+
+    sessions
+        .into_iter()
+        .map(|s| {
+            let data: serde_json::Value = serde_json::from_str(&s.data)?;
+            let client: ClientInfo = data
+                .get("client")
+                .cloned()
+                .map(serde_json::from_value)
+                .transpose()?
+                .unwrap_or_default();
+
+            Ok(ActiveSession {
+                handle: s.handle,
+                current: s.id == current_sid,
+                user_agent: client.user_agent,
+                ip: client.ip,
+                created_at: s.created_at,
+                updated_at: s.updated_at,
+                expires_at: s.expires_at,
+            })
+        })
+        .collect()
+}
+
+/// Check that the session `sid` has not expired and slide its `expires_at` forward. Returns
+/// false if the session does not exist or has expired, expired sessions are deleted.
+///
+/// Call this on every request that acts on the logged in user, and right after
+/// `ft_sdk::auth::provider::login`, so `expires_at` is set for new sessions.
+pub fn refresh(
+    conn: &mut ft_sdk::Connection,
+    sid: &str,
+    config: &Config,
+) -> Result<bool, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Session {
+        #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+        created_at: chrono::DateTime<chrono::Utc>,
+        #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    let session =
+        match diesel::sql_query("SELECT created_at, expires_at FROM fastn_session WHERE id = $1")
+            .bind::<diesel::sql_types::Text, _>(sid)
+            .get_result::<Session>(conn)
+        {
+            Ok(v) => v,
+            Err(diesel::result::Error::NotFound) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+    let now = ft_sdk::env::now();
+
+    // every call sweeps all expired sessions, not only `sid`, so apps that still look sessions up
+    // with `ft_sdk::auth::ud` stop finding them too
+    delete_expired(conn, now, config)?;
+
+    let Some(expires_at) = next_expiry(session.created_at, session.expires_at, now, config) else {
+        ft_sdk::println!("session expired, deleting it");
+        delete(conn, sid)?;
+        return Ok(false);
+    };
+
+    diesel::sql_query("UPDATE fastn_session SET updated_at = $1, expires_at = $2 WHERE id = $3")
+        .bind::<diesel::sql_types::Timestamptz, _>(now)
+        .bind::<diesel::sql_types::Timestamptz, _>(expires_at)
+        .bind::<diesel::sql_types::Text, _>(sid)
+        .execute(conn)?;
+
+    Ok(true)
+}
+
+/// When a session created at `created_at`, currently expiring at `expires_at`, expires if it is used
+/// `now`. `None` if it has already expired.
+///
+/// A session never lives past `created_at + session_max_age`, and with `session_idle_timeout` set
+/// every use pushes the expiry to `now + session_idle_timeout`, up to that deadline.
+fn next_expiry(
+    created_at: chrono::DateTime<chrono::Utc>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    now: chrono::DateTime<chrono::Utc>,
+    config: &Config,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    let deadline = created_at + chrono::TimeDelta::seconds(config.session_max_age);
+
+    if deadline <= now || expires_at.is_some_and(|e| e <= now) {
+        return None;
+    }
+
+    Some(match config.session_idle_timeout {
+        Some(idle) => std::cmp::min(deadline, now + chrono::TimeDelta::seconds(idle)),
+        None => deadline,
+    })
+}
+
+/// Delete every session that is past its `expires_at` or older than `session_max_age`.
+fn delete_expired(
+    conn: &mut ft_sdk::Connection,
+    now: chrono::DateTime<chrono::Utc>,
+    config: &Config,
+) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    diesel::sql_query("DELETE FROM fastn_session WHERE expires_at <= $1 OR created_at <= $2")
+        .bind::<diesel::sql_types::Timestamptz, _>(now)
+        .bind::<diesel::sql_types::Timestamptz, _>(
+            now - chrono::TimeDelta::seconds(config.session_max_age),
+        )
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Same as `ft_sdk::auth::ud` but expired sessions are rejected and active ones refreshed.
+///
+/// `ft_sdk::auth::ud` does not know about `expires_at`. Apps that use lets-auth sessions should
+/// call this instead, a session that expired is only guaranteed to be rejected by this function.
+/// Expired sessions are deleted whenever this runs, so `ft_sdk::auth::ud` stops accepting them
+/// too, but only once some request has gone through lets-auth after they expired.
+pub fn ud(
+    conn: &mut ft_sdk::Connection,
+    sid: Option<String>,
+    config: &Config,
+) -> Result<Option<ft_sdk::auth::UserData>, ft_sdk::Error> {
+    let sid = match sid {
+        Some(sid) if refresh(conn, &sid, config)? => sid,
+        _ => return Ok(None),
+    };
+
+    Ok(ft_sdk::auth::ud(ft_sdk::Cookie(Some(sid)), conn)?)
+}
+
+/// The session id from the cookie, if it is still usable. Pass this to
+/// `ft_sdk::auth::provider::login` so an expired session is never brought back to life.
+pub fn reusable(
+    conn: &mut ft_sdk::Connection,
+    sid: Option<String>,
+    config: &Config,
+) -> Result<Option<ft_sdk::SessionID>, ft_sdk::Error> {
+    match sid {
+        Some(sid) if refresh(conn, &sid, config)? => Ok(Some(ft_sdk::SessionID(sid))),
+        _ => Ok(None),
+    }
+}
+
+/// Delete the `fastn_session` row for `sid`. Once the row is gone the session id can not be used
+/// again, even if someone still has a copy of the cookie.
+pub fn delete(conn: &mut ft_sdk::Connection, sid: &str) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    diesel::sql_query("DELETE FROM fastn_session WHERE id = $1")
+        .bind::<diesel::sql_types::Text, _>(sid)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Delete the session of `uid` referred by `handle` (see `ActiveSession::handle`). Returns the
+/// id of the deleted session, `None` if no such session exists for this user.
+pub fn delete_by_handle(
+    conn: &mut ft_sdk::Connection,
+    uid: &ft_sdk::UserId,
+    handle: i64,
+) -> Result<Option<String>, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Deleted {
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        id: String,
+    }
+
+    let deleted =
+        diesel::sql_query("DELETE FROM fastn_session WHERE rowid = $1 AND uid = $2 RETURNING id")
+            .bind::<diesel::sql_types::BigInt, _>(handle)
+            .bind::<diesel::sql_types::BigInt, _>(uid.0)
+            .get_results::<Deleted>(conn)?;
+
+    Ok(deleted.into_iter().next().map(|d| d.id))
+}
+
+/// Delete every session of `uid` except `keep`. If `keep` is `None` all sessions of the user are
+/// deleted.
+pub fn delete_all_except(
+    conn: &mut ft_sdk::Connection,
+    uid: &ft_sdk::UserId,
+    keep: Option<&str>,
+) -> Result<usize, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    Ok(
+        diesel::sql_query("DELETE FROM fastn_session WHERE uid = $1 AND id IS NOT $2")
+            .bind::<diesel::sql_types::BigInt, _>(uid.0)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(keep)
+            .execute(conn)?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_client_ip() {
+        let ip = |forwarded_for, hops| super::client_ip(forwarded_for, Some("10.0.0.1"), hops);
+
+        assert_eq!(ip(None, 1), Some("10.0.0.1".to_string()));
+        assert_eq!(ip(Some("1.1.1.1"), 1), Some("1.1.1.1".to_string()));
+        // the first entry is whatever the client sent
+        assert_eq!(ip(Some("6.6.6.6, 1.1.1.1"), 1), Some("1.1.1.1".to_string()));
+        assert_eq!(
+            ip(Some("6.6.6.6, 1.1.1.1, 2.2.2.2"), 2),
+            Some("1.1.1.1".to_string())
+        );
+        assert_eq!(ip(Some("1.1.1.1"), 2), Some("1.1.1.1".to_string()));
+    }
+
+    #[test]
+    fn test_next_expiry() {
+        let at = |seconds| chrono::DateTime::from_timestamp(seconds, 0).unwrap();
+
+        let absolute = super::Config {
+            session_max_age: 100,
+            session_idle_timeout: None,
+        };
+
+        // without an idle timeout only the absolute deadline matters
+        assert_eq!(
+            super::next_expiry(at(0), None, at(10), &absolute),
+            Some(at(100))
+        );
+        assert_eq!(
+            super::next_expiry(at(0), Some(at(100)), at(99), &absolute),
+            Some(at(100))
+        );
+        assert_eq!(
+            super::next_expiry(at(0), Some(at(100)), at(100), &absolute),
+            None
+        );
+        // an old `expires_at` is not extended by a later use
+        assert_eq!(
+            super::next_expiry(at(0), Some(at(50)), at(60), &absolute),
+            None
+        );
+
+        let idle = super::Config {
+            session_max_age: 100,
+            session_idle_timeout: Some(30),
+        };
+
+        // every use slides the expiry by the idle timeout
+        assert_eq!(super::next_expiry(at(0), None, at(10), &idle), Some(at(40)));
+        assert_eq!(
+            super::next_expiry(at(0), Some(at(40)), at(39), &idle),
+            Some(at(69))
+        );
+        // not used within the idle timeout
+        assert_eq!(super::next_expiry(at(0), Some(at(40)), at(40), &idle), None);
+        // but never past the absolute deadline
+        assert_eq!(
+            super::next_expiry(at(0), Some(at(95)), at(90), &idle),
+            Some(at(100))
+        );
+        assert_eq!(
+            super::next_expiry(at(0), Some(at(120)), at(100), &idle),
+            None
+        );
+    }
+}
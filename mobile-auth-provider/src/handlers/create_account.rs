@@ -1,37 +1,115 @@
+/// Start creating an account, this is available on /mobile_auth_provider/create-account/ route
+///
+/// Nothing is stored for the user yet, a code is sent to `mobile_number` and the account is
+/// created by `verify_code` once the user enters it.
 #[ft_sdk::form]
 pub fn create_account(
     mut conn: ft_sdk::Connection,
     ft_sdk::Form(payload): ft_sdk::Form<CreateAccountPayload>,
+    client: common::session::ClientInfo,
+    ft_sdk::Config(config): ft_sdk::Config<mobile_auth::Config>,
+) -> ft_sdk::form::Result {
+    let next = payload.next.clone().unwrap_or_else(|| "/".to_string());
+    let account_meta = validate_and_account_meta(payload, &mut conn, &config)?;
+
+    mobile_auth::otp::send(
+        &mut conn,
+        &account_meta.mobile_number,
+        &mobile_auth::otp::Pending::CreateAccount {
+            username: account_meta.username,
+        },
+        &client,
+    )?;
+
+    ft_sdk::println!("Verification code sent to {}", account_meta.mobile_number);
+
+    ft_sdk::form::redirect(next)
+}
+
+/// Verify the code sent to `mobile_number` and log the user in, this is available on
+/// /mobile_auth_provider/verify-code/ route
+///
+/// The account is created with the number stored as verified if this finishes a `create_account`.
+/// The user is sent to `next`, or to the `next` passed to `login` if this finishes a login. Users
+/// with two-factor authentication are sent to the /two-factor/ page first, see
+/// `common::login::complete_login`.
+#[ft_sdk::form]
+#[expect(clippy::too_many_arguments)]
+pub fn verify_code(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Required(mobile_number): ft_sdk::Required<"mobile_number">,
+    ft_sdk::Required(code): ft_sdk::Required<"code">,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
     ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
     host: ft_sdk::Host,
+    client: common::session::ClientInfo,
+    app_url: ft_sdk::AppUrl,
+    ft_sdk::Config(config): ft_sdk::Config<mobile_auth::Config>,
 ) -> ft_sdk::form::Result {
-    let account_meta = validate_and_account_meta(payload, &mut conn)?;
-    ft_sdk::println!("Account meta done for {}", account_meta.username);
-
-    let uid = match account_meta.user_id.clone() {
-        Some(uid) => {
-            ft_sdk::auth::provider::update_user(
+    let mobile_number =
+        mobile_auth::phone::normalise(&mobile_number, config.mobile_default_region.as_deref())
+            .map_err(|e| ft_sdk::single_error("mobile_number", e))?;
+
+    let (uid, pending_next) = match mobile_auth::otp::verify(&mut conn, &mobile_number, &code)? {
+        mobile_auth::otp::Pending::CreateAccount { username } => {
+            // the username or number may have been taken while the code was on its way
+            let account_meta = validate_and_account_meta(
+                CreateAccountPayload {
+                    username,
+                    mobile_number,
+                    next: None,
+                },
                 &mut conn,
-                mobile_auth::PROVIDER_ID,
-                &uid,
-                account_meta.to_provider_data(),
-                true,
+                &config,
             )?;
-            uid
+            ft_sdk::println!("Account meta done for {}", account_meta.username);
+
+            let uid = match account_meta.user_id.clone() {
+                Some(uid) => {
+                    ft_sdk::auth::provider::update_user(
+                        &mut conn,
+                        mobile_auth::PROVIDER_ID,
+                        &uid,
+                        account_meta.to_provider_data(),
+                        true,
+                    )?;
+                    uid
+                }
+                None => ft_sdk::auth::provider::create_user(
+                    &mut conn,
+                    mobile_auth::PROVIDER_ID,
+                    account_meta.to_provider_data(),
+                )?,
+            };
+
+            (uid, None)
+        }
+        mobile_auth::otp::Pending::Login { user_id, next } => {
+            (ft_sdk::auth::UserId(user_id), Some(next))
         }
-        None => ft_sdk::auth::provider::create_user(
-            &mut conn,
-            mobile_auth::PROVIDER_ID,
-            account_meta.to_provider_data(),
-        )?,
     };
 
-    let ft_sdk::SessionID(sid) =
-        ft_sdk::auth::provider::login(&mut conn, &uid, sid.map(ft_sdk::SessionID))?;
-
-    ft_sdk::println!("Create User done for sid {sid}");
-
-    Ok(ft_sdk::form::redirect("/")?.with_cookie(common::session_cookie(sid.as_str(), host)?))
+    // an sms code is only the first factor, like a password: users who turned on two-factor
+    // authentication go through it here too, as they do with every other provider. Do not log
+    // the user in directly with `ft_sdk::auth::provider::login`.
+    let two_factor = common::login::user_two_factor_enabled(&mut conn, &uid)?;
+    ft_sdk::println!("Code verified for user {}, two factor: {two_factor}", uid.0);
+
+    let next = next.or(pending_next).unwrap_or_else(|| "/".to_string());
+
+    let (next, cookie) = common::login::complete_login(
+        &mut conn,
+        &uid,
+        two_factor,
+        sid,
+        next,
+        host,
+        &client,
+        &app_url,
+        &config.session,
+    )?;
+
+    Ok(ft_sdk::form::redirect(next)?.with_cookie(cookie))
 }
 
 struct CreateAccount {
@@ -49,65 +127,40 @@ impl CreateAccount {
             emails: vec![],
             verified_emails: vec![],
             profile_picture: None,
+            // only called once the number is verified, see `verify_code`
             custom: serde_json::json!({
-                "mobile_numbers": vec![self.mobile_number.clone()],
+                mobile_auth::MOBILE_NUMBERS_KEY: vec![self.mobile_number.clone()],
+                mobile_auth::VERIFIED_MOBILE_NUMBERS_KEY: vec![self.mobile_number.clone()],
             }),
         }
     }
 }
 
 fn validate_and_account_meta(
-    payload: CreateAccountPayload,
+    mut payload: CreateAccountPayload,
     conn: &mut ft_sdk::Connection,
+    config: &mobile_auth::Config,
 ) -> Result<CreateAccount, ft_sdk::Error> {
     let mut errors = std::collections::HashMap::new();
 
-    payload.validate(conn, &mut errors)?;
+    payload.validate(conn, &mut errors, config)?;
 
     if !errors.is_empty() {
         return Err(ft_sdk::SpecialError::Multi(errors).into());
     }
 
-    use diesel::prelude::*;
-
-    #[derive(diesel::QueryableByName)]
-    #[diesel(table_name = ft_sdk::auth::fastn_user)]
-    struct Identity {
-        identity: Option<String>,
-        id: i64,
-    }
-
     // check if the code is associated with a subscriber that is creating an account
     // if we find a user_id, it means the user is pre_verified
-    let user_id = match diesel::sql_query(format!(
-        r#"
-            SELECT
-                id, identity
-            FROM fastn_user
-            WHERE
-                EXISTS (
-                    SELECT 1
-                    FROM json_each ( data -> '{}' -> 'custom' -> 'mobile_numbers')
-                    WHERE value = $1
-                )
-            "#,
-        mobile_auth::PROVIDER_ID
-    ))
-    .bind::<diesel::sql_types::Text, _>(&payload.mobile_number)
-    .get_result::<Identity>(conn)
-    {
-        Ok(identity) => {
-            if identity.identity.is_some() {
+    let user_id = match user_by_mobile_number(conn, &payload.mobile_number)? {
+        Some(user) => {
+            if user.identity.is_some() {
                 return Err(
                     ft_sdk::single_error("mobile_number", "Mobile number already exists.").into(),
                 );
             }
-            Some(ft_sdk::auth::UserId(identity.id))
-        }
-        Err(diesel::result::Error::NotFound) => None,
-        Err(e) => {
-            return Err(e.into());
+            Some(ft_sdk::auth::UserId(user.id))
         }
+        None => None,
     };
 
     Ok(CreateAccount {
@@ -117,36 +170,82 @@ fn validate_and_account_meta(
     })
 }
 
+#[derive(diesel::QueryableByName)]
+#[diesel(table_name = ft_sdk::auth::fastn_user)]
+pub(crate) struct MobileUser {
+    pub(crate) identity: Option<String>,
+    pub(crate) id: i64,
+}
+
+/// The user `mobile_number`, in E.164 form, belongs to. A user without an `identity` is a
+/// subscriber that has not created an account yet.
+///
+/// Numbers stored before they were normalised are not found, the super user rewrites them once
+/// with `normalise_mobile_numbers`.
+pub(crate) fn user_by_mobile_number(
+    conn: &mut ft_sdk::Connection,
+    mobile_number: &str,
+) -> Result<Option<MobileUser>, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    Ok(diesel::sql_query(format!(
+        r#"
+            SELECT
+                id, identity
+            FROM fastn_user
+            WHERE
+                EXISTS (
+                    SELECT 1
+                    FROM json_each ( data -> '{}' -> 'custom' -> '{}')
+                    WHERE value = $1
+                )
+            "#,
+        mobile_auth::PROVIDER_ID,
+        mobile_auth::MOBILE_NUMBERS_KEY,
+    ))
+    .bind::<diesel::sql_types::Text, _>(mobile_number)
+    .get_result::<MobileUser>(conn)
+    .optional()?)
+}
+
 #[derive(serde::Deserialize)]
 pub struct CreateAccountPayload {
     pub(crate) username: String,
     pub(crate) mobile_number: String,
+    /// Where to go once the code is sent, the page the user enters the code on
+    #[serde(default)]
+    pub(crate) next: Option<String>,
 }
 
 impl CreateAccountPayload {
+    /// Also replaces `mobile_number` with its canonical form, which is what gets stored and looked
+    /// up
     pub(crate) fn validate(
-        &self,
+        &mut self,
         conn: &mut ft_sdk::Connection,
         errors: &mut std::collections::HashMap<String, String>,
+        config: &mobile_auth::Config,
     ) -> Result<(), ft_sdk::Error> {
-        validate_mobile_number(self.mobile_number.as_str(), errors)?;
+        if let Some(mobile_number) = validate_mobile_number(&self.mobile_number, config, errors) {
+            self.mobile_number = mobile_number;
+        }
         common::validate_identity("username", &self.username, conn, errors)?;
 
         Ok(())
     }
 }
 
+/// The E.164 form of `mobile_number`, see `phone::normalise`
 fn validate_mobile_number(
     mobile_number: &str,
+    config: &mobile_auth::Config,
     errors: &mut std::collections::HashMap<String, String>,
-) -> Result<(), ft_sdk::Error> {
-    let re = regex::Regex::new(r"^\d{10,12}$").unwrap();
-    if !re.is_match(mobile_number) {
-        errors.insert(
-            "mobile_number".to_string(),
-            "Invalid mobile number".to_string(),
-        );
+) -> Option<String> {
+    match mobile_auth::phone::normalise(mobile_number, config.mobile_default_region.as_deref()) {
+        Ok(mobile_number) => Some(mobile_number),
+        Err(e) => {
+            errors.insert("mobile_number".to_string(), e.to_string());
+            None
+        }
     }
-
-    Ok(())
 }
@@ -0,0 +1,52 @@
+/// Log in with a mobile number, this is available on /mobile_auth_provider/login/ route
+///
+/// A code is sent to `mobile_number`, the user is logged in by `create_account::verify_code` once
+/// they enter it, and sent to `next` like the email login does.
+#[ft_sdk::form]
+pub fn login(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Form(payload): ft_sdk::Form<LoginPayload>,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    client: common::session::ClientInfo,
+    ft_sdk::Config(config): ft_sdk::Config<mobile_auth::Config>,
+) -> ft_sdk::form::Result {
+    let mobile_number = mobile_auth::phone::normalise(
+        &payload.mobile_number,
+        config.mobile_default_region.as_deref(),
+    )
+    .map_err(|e| ft_sdk::single_error("mobile_number", e))?;
+
+    let user = match super::create_account::user_by_mobile_number(&mut conn, &mobile_number)? {
+        // subscribers without an account can not log in, they have to create one first
+        Some(user) if user.identity.is_some() => user,
+        _ => {
+            return Err(ft_sdk::single_error(
+                "mobile_number",
+                "No account is linked with the provided mobile number",
+            )
+            .into());
+        }
+    };
+
+    mobile_auth::otp::send(
+        &mut conn,
+        &mobile_number,
+        &mobile_auth::otp::Pending::Login {
+            user_id: user.id,
+            next: next.unwrap_or_else(|| "/".to_string()),
+        },
+        &client,
+    )?;
+
+    ft_sdk::println!("Login code sent to {mobile_number}");
+
+    ft_sdk::form::redirect(payload.next.unwrap_or_else(|| "/".to_string()))
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginPayload {
+    mobile_number: String,
+    /// Where to go once the code is sent, the page the user enters the code on
+    #[serde(default)]
+    next: Option<String>,
+}
@@ -1 +1,3 @@
 mod create_account;
+mod login;
+mod normalise_mobile_numbers;
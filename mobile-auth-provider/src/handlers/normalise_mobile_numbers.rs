@@ -0,0 +1,200 @@
+/// Rewrite the mobile numbers stored as plain digits by older versions in E.164 form, this is
+/// available on /mobile_auth_provider/normalise-mobile-numbers/ route. Only the super user
+/// (`super-user-id` in the config) can use it.
+///
+/// Logins and signups only look numbers up in E.164 form, so this has to run once after
+/// upgrading, with `mobile-default-region` set to the region the old numbers were entered in.
+/// Numbers that can not be normalised, see `phone::normalise_legacy`, are left as they are and
+/// counted in the log. Running it again only looks at the users that still have such numbers.
+#[ft_sdk::form]
+pub fn normalise_mobile_numbers(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    ft_sdk::Config(config): ft_sdk::Config<mobile_auth::Config>,
+) -> ft_sdk::form::Result {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct User {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        id: i64,
+    }
+
+    let ud = common::session::ud(&mut conn, sid, &config.session)?;
+
+    if ud.is_none_or(|ud| config.super_user_id != Some(ud.id)) {
+        return Err(
+            ft_sdk::unauthorised!("Only the super user can normalise mobile numbers.").into(),
+        );
+    }
+
+    // only users with a number that does not start with `+` need a change
+    let users: Vec<User> = diesel::sql_query(format!(
+        r#"
+            SELECT id
+            FROM fastn_user
+            WHERE
+                EXISTS (
+                    SELECT 1
+                    FROM json_each ( data -> '{provider}' -> 'custom' -> '{numbers}')
+                    WHERE value NOT LIKE '+%'
+                )
+                OR EXISTS (
+                    SELECT 1
+                    FROM json_each ( data -> '{provider}' -> 'custom' -> '{verified}')
+                    WHERE value NOT LIKE '+%'
+                )
+            "#,
+        provider = mobile_auth::PROVIDER_ID,
+        numbers = mobile_auth::MOBILE_NUMBERS_KEY,
+        verified = mobile_auth::VERIFIED_MOBILE_NUMBERS_KEY,
+    ))
+    .load(&mut conn)?;
+
+    let mut left = 0;
+    let mut failed = 0;
+
+    for user in users.iter() {
+        match normalise_user(
+            &mut conn,
+            &ft_sdk::UserId(user.id),
+            config.mobile_default_region.as_deref(),
+        ) {
+            Ok(n) => left += n,
+            Err(e) => {
+                ft_sdk::println!(
+                    "failed to normalise the mobile numbers of user {}: {e:?}",
+                    user.id
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    ft_sdk::println!(
+        "normalised the mobile numbers of {} users, {failed} failed, {left} numbers left as they are",
+        users.len() - failed
+    );
+
+    ft_sdk::form::redirect(next.unwrap_or_else(|| "/".to_string()))
+}
+
+/// Normalise the stored numbers of `user_id`, returns how many could not be normalised
+fn normalise_user(
+    conn: &mut ft_sdk::Connection,
+    user_id: &ft_sdk::UserId,
+    default_region: Option<&str>,
+) -> Result<usize, ft_sdk::Error> {
+    let mut data =
+        ft_sdk::auth::provider::user_data_by_id(conn, mobile_auth::PROVIDER_ID, user_id)?;
+
+    let custom = data.custom.as_object_mut().ok_or_else(|| {
+        ft_sdk::server_error!("custom data of user {} is not a json object", user_id.0)
+    })?;
+
+    let left = normalise_numbers(custom, default_region);
+
+    ft_sdk::auth::provider::update_user(conn, mobile_auth::PROVIDER_ID, user_id, data, false)?;
+
+    Ok(left)
+}
+
+/// Replace the legacy numbers in `custom`, the custom data of the mobile provider, with their
+/// E.164 form. A number that is then listed twice is kept once. Returns how many numbers could not
+/// be normalised.
+fn normalise_numbers(
+    custom: &mut serde_json::Map<String, serde_json::Value>,
+    default_region: Option<&str>,
+) -> usize {
+    let mut left = 0;
+
+    for key in [
+        mobile_auth::MOBILE_NUMBERS_KEY,
+        mobile_auth::VERIFIED_MOBILE_NUMBERS_KEY,
+    ] {
+        let Some(numbers) = custom.get_mut(key).and_then(|v| v.as_array_mut()) else {
+            continue;
+        };
+
+        let mut normalised = Vec::with_capacity(numbers.len());
+
+        for number in numbers.drain(..) {
+            let number = match number.as_str() {
+                Some(n) if !n.starts_with('+') => {
+                    match mobile_auth::phone::normalise_legacy(n, default_region) {
+                        Some(n) => serde_json::Value::String(n),
+                        None => {
+                            left += 1;
+                            number
+                        }
+                    }
+                }
+                _ => number,
+            };
+
+            if !normalised.contains(&number) {
+                normalised.push(number);
+            }
+        }
+
+        *numbers = normalised;
+    }
+
+    left
+}
+
+#[cfg(test)]
+mod tests {
+    fn normalise_numbers(custom: serde_json::Value) -> (serde_json::Value, usize) {
+        let mut custom = custom.as_object().unwrap().clone();
+        let left = super::normalise_numbers(&mut custom, Some("IN"));
+        (serde_json::Value::Object(custom), left)
+    }
+
+    #[test]
+    fn test_normalise_numbers() {
+        assert_eq!(
+            normalise_numbers(serde_json::json!({
+                "mobile_numbers": ["9876543210", "+918123456789", "12025550173"],
+                "verified_mobile_numbers": ["9876543210"],
+                "other": "9876543210",
+            })),
+            (
+                serde_json::json!({
+                    "mobile_numbers": ["+919876543210", "+918123456789", "+12025550173"],
+                    "verified_mobile_numbers": ["+919876543210"],
+                    "other": "9876543210",
+                }),
+                0
+            )
+        );
+
+        // the same number stored in both forms is kept once
+        assert_eq!(
+            normalise_numbers(serde_json::json!({
+                "mobile_numbers": ["+919876543210", "9876543210", "919876543210"],
+            })),
+            (
+                serde_json::json!({ "mobile_numbers": ["+919876543210"] }),
+                0
+            )
+        );
+
+        // numbers that can not be normalised are left as they are
+        assert_eq!(
+            normalise_numbers(serde_json::json!({
+                "mobile_numbers": ["98765 43210", "12345"],
+            })),
+            (
+                serde_json::json!({ "mobile_numbers": ["98765 43210", "12345"] }),
+                2
+            )
+        );
+
+        assert_eq!(
+            normalise_numbers(serde_json::json!({})),
+            (serde_json::json!({}), 0)
+        );
+    }
+}
@@ -1,4 +1,37 @@
 extern crate self as mobile_auth;
 mod handlers;
+mod otp;
+mod phone;
+mod sms;
 
 pub const PROVIDER_ID: &str = "mobile";
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    #[serde(flatten)]
+    pub(crate) session: common::session::Config,
+    /// Region of mobile numbers entered without a country code, e.g. `IN`. If not set, numbers
+    /// have to start with `+<country code>`. See `phone::normalise`.
+    #[serde(default)]
+    pub(crate) mobile_default_region: Option<String>,
+    /// The user that can use admin only handlers like `normalise_mobile_numbers`
+    #[serde(default)]
+    pub(crate) super_user_id: Option<i64>,
+}
+
+/// Custom data key with every mobile number of the user
+pub const MOBILE_NUMBERS_KEY: &str = "mobile_numbers";
+/// Custom data key with the mobile numbers the user has proven they own, see `otp`
+pub const VERIFIED_MOBILE_NUMBERS_KEY: &str = "verified_mobile_numbers";
+
+/// Codes sent to the same mobile number
+pub const MOBILE_RATE_LIMIT: common::rate_limit::Limit = common::rate_limit::Limit {
+    max: 3,
+    window_seconds: 60 * 60,
+};
+/// Codes requested from the same IP address
+pub const IP_RATE_LIMIT: common::rate_limit::Limit = common::rate_limit::Limit {
+    max: 20,
+    window_seconds: 60 * 60,
+};
@@ -0,0 +1,294 @@
+//! One time codes sent by sms to prove the user owns a mobile number. There is at most one code
+//! per number in `fastn_mobile_otp`, sending a new one replaces the old one.
+
+/// Wrong codes allowed before the code stops working and a new one has to be requested
+const MAX_ATTEMPTS: i64 = 5;
+
+/// What to do once the number is verified, stored along with the code
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(tag = "purpose", rename_all = "kebab-case")]
+pub enum Pending {
+    CreateAccount {
+        username: String,
+    },
+    /// `next` is where to send the user once they are logged in
+    Login {
+        user_id: i64,
+        next: String,
+    },
+}
+
+/// Send a new code to `mobile_number`, `pending` is returned by `verify` once the user enters it.
+/// Codes sent to a number, and from the IP address of the client, are rate limited so this can
+/// not be used to flood a phone with messages.
+pub fn send(
+    conn: &mut ft_sdk::Connection,
+    mobile_number: &str,
+    pending: &Pending,
+    client: &common::session::ClientInfo,
+) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    // a missing gateway is a setup error, no code is stored or counted for it
+    let sender = mobile_auth::sms::sender()?;
+
+    if let Some(ip) = client.ip.as_deref() {
+        common::rate_limit::check(
+            conn,
+            "mobile-otp",
+            &format!("ip:{ip}"),
+            &mobile_auth::IP_RATE_LIMIT,
+            "mobile_number",
+        )?;
+    }
+
+    common::rate_limit::check(
+        conn,
+        "mobile-otp",
+        &format!("mobile:{mobile_number}"),
+        &mobile_auth::MOBILE_RATE_LIMIT,
+        "mobile_number",
+    )?;
+
+    let code = common::code::generate();
+
+    diesel::sql_query(
+        r#"
+        INSERT INTO fastn_mobile_otp (mobile_number, code_hash, pending, attempts, created_at)
+        VALUES ($1, $2, $3, 0, $4)
+        ON CONFLICT (mobile_number) DO UPDATE SET
+            code_hash = excluded.code_hash,
+            pending = excluded.pending,
+            attempts = 0,
+            created_at = excluded.created_at
+        "#,
+    )
+    .bind::<diesel::sql_types::Text, _>(mobile_number)
+    .bind::<diesel::sql_types::Text, _>(hash(&code))
+    .bind::<diesel::sql_types::Text, _>(serde_json::to_string(pending)?)
+    .bind::<diesel::sql_types::Timestamptz, _>(ft_sdk::env::now())
+    .execute(conn)?;
+
+    sender.send(
+        mobile_number,
+        &format!(
+            "{code} is your verification code. It expires in {} minutes.",
+            expiry_minutes()
+        ),
+    )
+}
+
+/// Check `code` against the code sent to `mobile_number`. The code works only once, and only
+/// `MAX_ATTEMPTS` wrong codes are allowed before it stops working.
+pub fn verify(
+    conn: &mut ft_sdk::Connection,
+    mobile_number: &str,
+    code: &str,
+) -> Result<Pending, ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct Otp {
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        code_hash: String,
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        pending: String,
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        attempts: i64,
+        #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+        created_at: chrono::DateTime<chrono::Utc>,
+    }
+
+    let otp = match diesel::sql_query(
+        "SELECT code_hash, pending, attempts, created_at FROM fastn_mobile_otp WHERE \
+         mobile_number = $1",
+    )
+    .bind::<diesel::sql_types::Text, _>(mobile_number)
+    .get_result::<Otp>(conn)
+    {
+        Ok(otp) => otp,
+        Err(diesel::result::Error::NotFound) => {
+            return Err(ft_sdk::single_error("code", "Incorrect code.").into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let outcome = check(
+        &otp.code_hash,
+        otp.attempts,
+        otp.created_at,
+        code,
+        ft_sdk::env::now(),
+        expiry_minutes(),
+    );
+
+    // the code works only once, whether it was used or it stopped working
+    if outcome != Outcome::Incorrect {
+        remove(conn, mobile_number)?;
+    }
+
+    match outcome {
+        Outcome::Accepted => Ok(serde_json::from_str(&otp.pending)?),
+        Outcome::Incorrect => {
+            diesel::sql_query(
+                "UPDATE fastn_mobile_otp SET attempts = attempts + 1 WHERE mobile_number = $1",
+            )
+            .bind::<diesel::sql_types::Text, _>(mobile_number)
+            .execute(conn)?;
+
+            Err(ft_sdk::single_error("code", "Incorrect code.").into())
+        }
+        Outcome::Expired => {
+            Err(ft_sdk::single_error("code", "Code expired. Please request a new one.").into())
+        }
+        Outcome::TooManyAttempts => Err(ft_sdk::single_error(
+            "code",
+            "Too many incorrect attempts. Please request a new code.",
+        )
+        .into()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Accepted,
+    Incorrect,
+    Expired,
+    TooManyAttempts,
+}
+
+/// What entering `code` at `now` does to a code stored with `code_hash`, that was sent at
+/// `sent_at` and has had `attempts` wrong codes entered for it
+fn check(
+    code_hash: &str,
+    attempts: i64,
+    sent_at: chrono::DateTime<chrono::Utc>,
+    code: &str,
+    now: chrono::DateTime<chrono::Utc>,
+    expiry_minutes: i64,
+) -> Outcome {
+    if sent_at + chrono::TimeDelta::minutes(expiry_minutes) <= now {
+        Outcome::Expired
+    } else if attempts >= MAX_ATTEMPTS {
+        Outcome::TooManyAttempts
+    } else if hash(code.trim()) != code_hash {
+        Outcome::Incorrect
+    } else {
+        Outcome::Accepted
+    }
+}
+
+fn remove(conn: &mut ft_sdk::Connection, mobile_number: &str) -> Result<(), ft_sdk::Error> {
+    use diesel::prelude::*;
+
+    diesel::sql_query("DELETE FROM fastn_mobile_otp WHERE mobile_number = $1")
+        .bind::<diesel::sql_types::Text, _>(mobile_number)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Codes are short lived and attempts are limited, a fast hash is enough to keep them out of the
+/// database
+fn hash(code: &str) -> String {
+    use sha2::Digest;
+
+    format!("{:x}", sha2::Sha256::digest(code))
+}
+
+fn expiry_minutes() -> i64 {
+    ft_sdk::env::var("MOBILE_OTP_EXPIRE_MINUTES".to_string())
+        .map(|v| {
+            v.parse()
+                .expect("MOBILE_OTP_EXPIRE_MINUTES should be a number")
+        })
+        .unwrap_or(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Outcome;
+
+    fn at(minute: u32) -> chrono::DateTime<chrono::Utc> {
+        use chrono::TimeZone;
+
+        chrono::Utc
+            .with_ymd_and_hms(2025, 1, 1, 10, minute, 0)
+            .unwrap()
+    }
+
+    /// Enter `code` `minutes` after a code 123456 was sent, with `attempts` wrong codes so far
+    fn check(code: &str, attempts: i64, minutes: u32) -> Outcome {
+        super::check(
+            &super::hash("123456"),
+            attempts,
+            at(0),
+            code,
+            at(minutes),
+            10,
+        )
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check("123456", 0, 0), Outcome::Accepted);
+        assert_eq!(check(" 123456 ", 0, 9), Outcome::Accepted);
+        assert_eq!(check("654321", 0, 0), Outcome::Incorrect);
+        assert_eq!(check("", 0, 0), Outcome::Incorrect);
+    }
+
+    #[test]
+    fn test_check_expired() {
+        assert_eq!(check("123456", 0, 10), Outcome::Expired);
+        assert_eq!(check("123456", 0, 59), Outcome::Expired);
+        // an expired code is not checked at all
+        assert_eq!(check("654321", 0, 10), Outcome::Expired);
+    }
+
+    #[test]
+    fn test_check_max_attempts() {
+        assert_eq!(
+            check("123456", super::MAX_ATTEMPTS - 1, 0),
+            Outcome::Accepted
+        );
+        // once used up, even the right code is refused
+        assert_eq!(
+            check("123456", super::MAX_ATTEMPTS, 0),
+            Outcome::TooManyAttempts
+        );
+    }
+
+    #[test]
+    fn test_hash() {
+        // the stored hash never contains the code itself
+        assert_ne!(super::hash("123456"), "123456");
+        assert_eq!(super::hash("123456"), super::hash("123456"));
+        assert_ne!(super::hash("123456"), super::hash("123457"));
+    }
+
+    #[test]
+    fn test_pending_round_trip() {
+        let login = super::Pending::Login {
+            user_id: 7,
+            next: "/dashboard/".to_string(),
+        };
+        let json = serde_json::to_value(&login).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"purpose": "login", "user_id": 7, "next": "/dashboard/"})
+        );
+        assert!(matches!(
+            serde_json::from_value(json).unwrap(),
+            super::Pending::Login { user_id: 7, next } if next == "/dashboard/"
+        ));
+
+        let create = super::Pending::CreateAccount {
+            username: "jane".to_string(),
+        };
+        let json = serde_json::to_string(&create).unwrap();
+        assert!(matches!(
+            serde_json::from_str(&json).unwrap(),
+            super::Pending::CreateAccount { username } if username == "jane"
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+//! Mobile numbers are stored in E.164 form (`+<country code><number>`), so the same number typed
+//! with different spacing, punctuation or prefixes is found by the same lookup.
+
+/// Parse `number` and return it in E.164 form. Numbers without a leading `+` are read as numbers
+/// of `default_region` (ISO 3166 code, e.g. `IN`), and are rejected if it is not set.
+pub fn normalise(number: &str, default_region: Option<&str>) -> Result<String, &'static str> {
+    let region = match default_region {
+        Some(region) => Some(
+            region
+                .trim()
+                .to_uppercase()
+                .parse::<phonenumber::country::Id>()
+                .map_err(|_| "Invalid default region")?,
+        ),
+        None => None,
+    };
+
+    let number = number.trim();
+
+    if region.is_none() && !number.starts_with('+') {
+        return Err("Mobile number must start with the country code, e.g. +44");
+    }
+
+    let parsed = phonenumber::parse(region, number).map_err(|_| "Invalid mobile number")?;
+
+    if !phonenumber::is_valid(&parsed) {
+        return Err("Invalid mobile number");
+    }
+
+    Ok(parsed.format().mode(phonenumber::Mode::E164).to_string())
+}
+
+/// Numbers stored before `normalise` was used are plain digits (`^\d{10,12}$`), with or without
+/// the country code. The E.164 form of `stored` if it is such a number. `None` if it is not, or if
+/// it is a valid number both with and without the country code and the two are different numbers.
+pub fn normalise_legacy(stored: &str, default_region: Option<&str>) -> Option<String> {
+    if stored.len() < 10 || stored.len() > 12 || !stored.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let national = normalise(stored, default_region).ok();
+    let international = normalise(&format!("+{stored}"), None).ok();
+
+    match (national, international) {
+        (Some(national), Some(international)) if national != international => None,
+        (national, international) => national.or(international),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_normalise() {
+        assert_eq!(
+            super::normalise("+44 20 7946 0958", None),
+            Ok("+442079460958".to_string())
+        );
+        assert_eq!(
+            super::normalise("020 7946 0958", Some("gb")),
+            Ok("+442079460958".to_string())
+        );
+        assert_eq!(
+            super::normalise("(202) 555-0173", Some("US")),
+            Ok("+12025550173".to_string())
+        );
+        assert_eq!(
+            super::normalise("+1 202-555-0173", Some("GB")),
+            Ok("+12025550173".to_string())
+        );
+        assert_eq!(
+            super::normalise("020 7946 0958", None),
+            Err("Mobile number must start with the country code, e.g. +44")
+        );
+        assert_eq!(
+            super::normalise("12345", Some("GB")),
+            Err("Invalid mobile number")
+        );
+        assert_eq!(
+            super::normalise("not a number", Some("GB")),
+            Err("Invalid mobile number")
+        );
+    }
+
+    #[test]
+    fn test_normalise_legacy() {
+        let normalise_legacy = |stored, region| super::normalise_legacy(stored, region);
+        let some = |n: &str| Some(n.to_string());
+
+        // without the country code, read as a number of the default region
+        assert_eq!(
+            normalise_legacy("9876543210", Some("IN")),
+            some("+919876543210")
+        );
+        assert_eq!(
+            normalise_legacy("2025550173", Some("US")),
+            some("+12025550173")
+        );
+        // with the country code
+        assert_eq!(
+            normalise_legacy("919876543210", Some("IN")),
+            some("+919876543210")
+        );
+        assert_eq!(
+            normalise_legacy("919876543210", None),
+            some("+919876543210")
+        );
+        assert_eq!(
+            normalise_legacy("12025550173", Some("IN")),
+            some("+12025550173")
+        );
+
+        assert_eq!(normalise_legacy("9876543210", None), None);
+        // a US number, or an Egyptian one with the country code
+        assert_eq!(normalise_legacy("2015550173", Some("US")), None);
+        // already normalised, or not a plain digits number
+        assert_eq!(normalise_legacy("+919876543210", Some("IN")), None);
+        assert_eq!(normalise_legacy("98765 43210", Some("IN")), None);
+        assert_eq!(normalise_legacy("12345", Some("IN")), None);
+    }
+}
@@ -0,0 +1,102 @@
+//! Sending text messages. The gateway is picked by `sender`, from the environment:
+//!
+//! - `SMS_GATEWAY_URL` set: `HttpSender`, every message is POSTed to it as
+//!   `{"to": "<mobile number>", "body": "<message>"}`, with `SMS_GATEWAY_TOKEN` (if set) as a
+//!   bearer token. Put a small adapter in front of your SMS provider if its API looks different.
+//! - `SMS_SINK=log`: `LogSender`, messages are only printed to the log. This is meant for local
+//!   development and tests, where the code can be read from the log.
+//!
+//! With neither set, sending fails. Codes are never only logged unless that was asked for.
+
+pub trait Sender {
+    fn send(&self, to: &str, body: &str) -> Result<(), ft_sdk::Error>;
+}
+
+/// The sender configured in the environment, see the module docs
+pub fn sender() -> Result<Box<dyn Sender>, ft_sdk::Error> {
+    sender_for(
+        ft_sdk::env::var("SMS_GATEWAY_URL".to_string()),
+        ft_sdk::env::var("SMS_GATEWAY_TOKEN".to_string()),
+        ft_sdk::env::var("SMS_SINK".to_string()).as_deref(),
+    )
+}
+
+/// The sender for `SMS_GATEWAY_URL`, `SMS_GATEWAY_TOKEN` and `SMS_SINK` set to these values
+fn sender_for(
+    url: Option<String>,
+    token: Option<String>,
+    sink: Option<&str>,
+) -> Result<Box<dyn Sender>, ft_sdk::Error> {
+    if let Some(url) = url {
+        return Ok(Box::new(HttpSender { url, token }));
+    }
+
+    match sink {
+        Some("log") => Ok(Box::new(LogSender)),
+        Some(sink) => {
+            Err(ft_sdk::server_error!("unknown SMS_SINK: {sink}, only `log` is supported").into())
+        }
+        None => Err(ft_sdk::server_error!(
+            "sms is not configured, set SMS_GATEWAY_URL, or SMS_SINK=log for development"
+        )
+        .into()),
+    }
+}
+
+pub struct HttpSender {
+    url: String,
+    token: Option<String>,
+}
+
+impl Sender for HttpSender {
+    fn send(&self, to: &str, body: &str) -> Result<(), ft_sdk::Error> {
+        let mut request = http::Request::builder()
+            .method(http::Method::POST)
+            .uri(&self.url)
+            .header(http::header::CONTENT_TYPE, "application/json");
+
+        if let Some(token) = self.token.as_deref() {
+            request = request.header(http::header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+
+        let request = request.body(bytes::Bytes::from(serde_json::to_vec(
+            &serde_json::json!({ "to": to, "body": body }),
+        )?))?;
+
+        let response = ft_sdk::http::send(request)
+            .map_err(|e| ft_sdk::server_error!("sms gateway request failed: {e:?}"))?;
+
+        if !response.status().is_success() {
+            ft_sdk::println!("sms gateway returned {}", response.status());
+            return Err(ft_sdk::server_error!("failed to send sms: {}", response.status()).into());
+        }
+
+        Ok(())
+    }
+}
+
+pub struct LogSender;
+
+impl Sender for LogSender {
+    fn send(&self, to: &str, body: &str) -> Result<(), ft_sdk::Error> {
+        ft_sdk::println!("SMS_SINK is log, not sending sms to {to}: {body}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn url() -> Option<String> {
+        Some("https://sms.example.com/send".to_string())
+    }
+
+    #[test]
+    fn test_sender_for() {
+        assert!(super::sender_for(url(), None, None).is_ok());
+        assert!(super::sender_for(url(), None, Some("log")).is_ok());
+        assert!(super::sender_for(None, None, Some("log")).is_ok());
+        assert!(super::sender_for(None, None, Some("stdout")).is_err());
+        assert!(super::sender_for(None, Some("token".to_string()), None).is_err());
+        assert!(super::sender_for(None, None, None).is_err());
+    }
+}
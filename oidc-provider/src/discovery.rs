@@ -0,0 +1,123 @@
+/// The parts of the OpenID Provider Metadata (OpenID Connect Discovery 1.0, section 3) we use.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Document {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    #[serde(default)]
+    pub code_challenge_methods_supported: Vec<String>,
+}
+
+/// How long a discovery document is used before it is fetched again
+const CACHE_SECONDS: i64 = 60 * 60;
+
+/// Discovery documents by issuer, with the time they were fetched in seconds since epoch. Both
+/// `login` and `callback` need the document of the issuer, and it rarely changes.
+static CACHE: std::sync::Mutex<std::collections::BTreeMap<String, (i64, Document)>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+/// The discovery document of `issuer`, from the cache if it was fetched in the last
+/// `CACHE_SECONDS`.
+pub fn fetch(issuer: &str) -> Result<Document, ft_sdk::Error> {
+    let now = ft_sdk::env::now().timestamp();
+
+    let cached = CACHE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .get(issuer)
+        .filter(|(fetched_at, _)| fetched_at + CACHE_SECONDS > now)
+        .map(|(_, document)| document.clone());
+
+    if let Some(document) = cached {
+        return Ok(document);
+    }
+
+    let document = fetch_uncached(issuer)?;
+
+    CACHE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .insert(issuer.to_string(), (now, document.clone()));
+
+    Ok(document)
+}
+
+/// Fetch and check the discovery document of `issuer`.
+fn fetch_uncached(issuer: &str) -> Result<Document, ft_sdk::Error> {
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+
+    let request = http::Request::builder()
+        .method(http::Method::GET)
+        .uri(url)
+        .header(http::header::ACCEPT, "application/json")
+        .body(bytes::Bytes::new())?;
+
+    let document: Document = common::fetch::json(request)?;
+    document.validate(issuer)?;
+
+    Ok(document)
+}
+
+impl Document {
+    /// The document must be about the issuer we asked for (section 4.3), otherwise a provider
+    /// could vouch for users of another one.
+    fn validate(&self, issuer: &str) -> Result<(), ft_sdk::Error> {
+        if self.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+            return Err(ft_sdk::server_error!(
+                "discovery document is for issuer {}, expected {issuer}",
+                self.issuer
+            )
+            .into());
+        }
+
+        // an empty list means the provider did not say, most support S256 anyway
+        if !self.code_challenge_methods_supported.is_empty()
+            && !self
+                .code_challenge_methods_supported
+                .iter()
+                .any(|m| m == "S256")
+        {
+            return Err(ft_sdk::server_error!("{issuer} does not support PKCE with S256").into());
+        }
+
+        Ok(())
+    }
+}
+
+/// JSON Web Key Set of the provider, fetched from `Document::jwks_uri`
+#[derive(serde::Deserialize, Debug)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// A key of the `Jwks`. Only RSA keys are used, the rest are skipped.
+#[derive(serde::Deserialize, Debug)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default)]
+    pub kid: Option<String>,
+    #[serde(default, rename = "use")]
+    pub use_: Option<String>,
+    #[serde(default)]
+    pub alg: Option<String>,
+    /// RSA modulus, base64url encoded
+    #[serde(default)]
+    pub n: Option<String>,
+    /// RSA exponent, base64url encoded
+    #[serde(default)]
+    pub e: Option<String>,
+}
+
+pub fn fetch_jwks(document: &Document) -> Result<Jwks, ft_sdk::Error> {
+    let request = http::Request::builder()
+        .method(http::Method::GET)
+        .uri(&document.jwks_uri)
+        .header(http::header::ACCEPT, "application/json")
+        .body(bytes::Bytes::new())?;
+
+    common::fetch::json(request)
+}
@@ -0,0 +1,165 @@
+/// The identity provider sends the user back here after they log in, this is available on
+/// /oidc_provider/callback/ route
+///
+/// The `code` is exchanged for an `id_token` along with the PKCE code verifier. The token is
+/// verified against the provider keys, and the user is found by the `sub` claim. If this is their
+/// first login, the identity is linked to the logged in user, or a user is created under the
+/// provider id (see `common::link::resolve`). Users with two-factor authentication still have to
+/// enter their code, see `common::login::complete_login`.
+#[ft_sdk::processor]
+#[expect(clippy::too_many_arguments)]
+pub fn callback(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Query(code): ft_sdk::Query<"code", Option<String>>,
+    ft_sdk::Query(state): ft_sdk::Query<"state", Option<String>>,
+    ft_sdk::Query(error): ft_sdk::Query<"error", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    host: ft_sdk::Host,
+    client: common::session::ClientInfo,
+    app_url: ft_sdk::AppUrl,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::processor::Result {
+    let sid = sid.ok_or_else(|| ft_sdk::single_error("state", "Login was not started."))?;
+
+    let pending: super::PendingLogin =
+        common::session::get_data(&mut conn, &sid, super::PENDING_LOGIN_KEY)?
+            .ok_or_else(|| ft_sdk::single_error("state", "Login was not started."))?;
+
+    // state, nonce and code verifier are single use
+    common::session::remove_data(&mut conn, &sid, super::PENDING_LOGIN_KEY)?;
+
+    if state.as_deref() != Some(pending.state.as_str()) {
+        ft_sdk::println!("oidc state mismatch");
+        return Err(ft_sdk::single_error("state", "Invalid state, please try again.").into());
+    }
+
+    if let Some(error) = error {
+        ft_sdk::println!("identity provider returned error: {error}");
+        return Err(ft_sdk::single_error("code", "Login was cancelled.").into());
+    }
+
+    let code = code.ok_or_else(|| ft_sdk::single_error("code", "Missing code."))?;
+
+    let provider = config.provider(&pending.provider)?;
+    let document = oidc_provider::discovery::fetch(&provider.issuer)?;
+
+    let id_token = exchange_code(
+        provider,
+        &document,
+        &code,
+        &pending.code_verifier,
+        &super::callback_url(&app_url)?,
+    )?;
+
+    let jwks = oidc_provider::discovery::fetch_jwks(&document)?;
+
+    let claims = oidc_provider::id_token::verify_signature(&id_token, &jwks)
+        .and_then(|claims| {
+            claims.validate(
+                &provider.issuer,
+                &provider.client_id,
+                &pending.nonce,
+                ft_sdk::env::now().timestamp(),
+            )?;
+            Ok(claims)
+        })
+        .map_err(|e| {
+            ft_sdk::println!("id_token rejected: {e}");
+            ft_sdk::single_error("code", "Login failed, please try again.")
+        })?;
+
+    let data = claims.to_provider_data(&provider.id);
+
+    let existing = match ft_sdk::auth::provider::user_data_by_custom_attribute(
+        &mut conn,
+        &provider.id,
+        oidc_provider::SUBJECT_KEY,
+        &claims.sub,
+    ) {
+        Ok((user_id, _)) => Some(user_id),
+        Err(ft_sdk::auth::UserDataError::NoDataFound) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    // a logged in user going through this flow links the identity to their account
+    let logged_in = common::session::ud(&mut conn, Some(sid.clone()), &config.session)?
+        .map(|ud| ft_sdk::UserId(ud.id));
+
+    let user_id = common::link::resolve(
+        &mut conn,
+        &provider.id,
+        data,
+        existing,
+        logged_in,
+        &config.link,
+    )?;
+
+    // two-factor authentication set up with the email provider applies to these logins too
+    let two_factor = common::login::user_two_factor_enabled(&mut conn, &user_id)?;
+
+    let (next, cookie) = common::login::complete_login(
+        &mut conn,
+        &user_id,
+        two_factor,
+        Some(sid),
+        pending.next,
+        host,
+        &client,
+        &app_url,
+        &config.session,
+    )?;
+
+    Ok(ft_sdk::processor::temporary_redirect(next)?.with_cookie(cookie))
+}
+
+/// Exchange the authorization `code` for an id_token at the token endpoint
+fn exchange_code(
+    provider: &oidc_provider::ProviderConfig,
+    document: &oidc_provider::discovery::Document,
+    code: &str,
+    code_verifier: &str,
+    redirect_uri: &str,
+) -> Result<String, ft_sdk::Error> {
+    #[derive(serde::Deserialize)]
+    struct TokenResponse {
+        id_token: Option<String>,
+        error: Option<String>,
+        error_description: Option<String>,
+    }
+
+    let mut params = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", provider.client_id.as_str()),
+        ("code_verifier", code_verifier),
+    ];
+
+    if let Some(secret) = provider.client_secret.as_deref() {
+        params.push(("client_secret", secret));
+    }
+
+    let request = http::Request::builder()
+        .method(http::Method::POST)
+        .uri(&document.token_endpoint)
+        .header(http::header::ACCEPT, "application/json")
+        .header(
+            http::header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded",
+        )
+        .body(common::fetch::form_body(&params))?;
+
+    let response: TokenResponse = common::fetch::json(request)?;
+
+    match response.id_token {
+        Some(token) => Ok(token),
+        None => {
+            ft_sdk::println!(
+                "token exchange failed: {:?}: {:?}",
+                response.error,
+                response.error_description
+            );
+            Err(ft_sdk::single_error("code", "Login failed, please try again.").into())
+        }
+    }
+}
@@ -0,0 +1,70 @@
+/// Start login with the identity provider `provider` (the `id` in `ProviderConfig`), this is
+/// available on /oidc_provider/login/ route
+///
+/// The user is sent to the authorization endpoint of the provider, which sends them back to
+/// `callback`. The `state`, `nonce` and PKCE code verifier are kept in the session and checked in
+/// the callback.
+#[ft_sdk::processor]
+pub fn login(
+    mut conn: ft_sdk::Connection,
+    ft_sdk::Query(provider): ft_sdk::Query<"provider">,
+    ft_sdk::Query(next): ft_sdk::Query<"next", Option<String>>,
+    ft_sdk::Cookie(sid): ft_sdk::Cookie<{ ft_sdk::auth::SESSION_KEY }>,
+    host: ft_sdk::Host,
+    app_url: ft_sdk::AppUrl,
+    ft_sdk::Config(config): ft_sdk::Config<crate::Config>,
+) -> ft_sdk::processor::Result {
+    let provider = config.provider(&provider)?;
+    let document = oidc_provider::discovery::fetch(&provider.issuer)?;
+
+    // the pending login has to outlive this request, so an anonymous session is created if
+    // needed
+    let sid = match common::session::reusable(&mut conn, sid, &config.session)? {
+        Some(ft_sdk::SessionID(sid)) => sid,
+        None => common::session::create_anonymous(&mut conn, &config.session)?,
+    };
+
+    let pending = super::PendingLogin {
+        provider: provider.id.clone(),
+        state: ft_sdk::Rng::generate_key(32),
+        nonce: ft_sdk::Rng::generate_key(32),
+        code_verifier: oidc_provider::pkce::verifier(),
+        next: next.unwrap_or_else(|| "/".to_string()),
+    };
+
+    common::session::set_data(&mut conn, &sid, super::PENDING_LOGIN_KEY, &pending)?;
+
+    let scopes = if provider.scopes.split(' ').any(|s| s == "openid") {
+        provider.scopes.clone()
+    } else {
+        format!("openid {}", provider.scopes)
+    };
+
+    let params = [
+        ("response_type", "code"),
+        ("client_id", provider.client_id.as_str()),
+        ("redirect_uri", super::callback_url(&app_url)?.as_str()),
+        ("scope", scopes.as_str()),
+        ("state", pending.state.as_str()),
+        ("nonce", pending.nonce.as_str()),
+        (
+            "code_challenge",
+            oidc_provider::pkce::challenge(&pending.code_verifier).as_str(),
+        ),
+        ("code_challenge_method", "S256"),
+    ]
+    .iter()
+    .map(|(k, v)| format!("{k}={}", common::fetch::url_encode(v)))
+    .collect::<Vec<_>>()
+    .join("&");
+
+    let separator = if document.authorization_endpoint.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    let url = format!("{}{separator}{params}", document.authorization_endpoint);
+
+    let cookie = common::session_cookie(sid.as_str(), host, &config.session)?;
+    Ok(ft_sdk::processor::temporary_redirect(url)?.with_cookie(cookie))
+}
@@ -0,0 +1,24 @@
+mod callback;
+mod login;
+
+/// Session data key for the `PendingLogin` of an ongoing login
+const PENDING_LOGIN_KEY: &str = "oidc-login";
+
+/// Stored in the session between `login` and `callback`
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct PendingLogin {
+    provider: String,
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    next: String,
+}
+
+/// The redirect uri sent to the identity provider, it has to match the one registered there
+fn callback_url(app_url: &ft_sdk::AppUrl) -> Result<String, ft_sdk::Error> {
+    Ok(app_url
+        .join(oidc_provider::CALLBACK_PATH)
+        .inspect_err(|e| {
+            ft_sdk::println!("oidc.wasm: failed to join url: {:?}", e);
+        })?)
+}
@@ -0,0 +1,61 @@
+#![deny(unused_crate_dependencies)]
+#![forbid(unsafe_code)]
+
+//! OpenID Connect relying party. Any OIDC compliant identity provider (Google, Microsoft,
+//! Keycloak etc.) can be used to log in, see `Config` for how to set one up.
+
+extern crate self as oidc_provider;
+
+mod discovery;
+mod handlers;
+mod id_token;
+mod pkce;
+
+/// Path of `handlers::callback`, the redirect uri to register with the identity provider is this
+/// path joined to the app url.
+pub const CALLBACK_PATH: &str = "/oidc_provider/callback/";
+/// Custom data key that stores the `sub` claim, the id of the user at the identity provider
+pub const SUBJECT_KEY: &str = "oidc_sub";
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    #[serde(flatten)]
+    pub(crate) session: common::session::Config,
+    #[serde(flatten)]
+    pub(crate) link: common::link::Config,
+    #[serde(default)]
+    pub(crate) oidc_providers: Vec<ProviderConfig>,
+}
+
+/// One identity provider users can log in with.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProviderConfig {
+    /// Used in the login url, and as the provider id the user data is stored under in
+    /// `fastn_user.data`, e.g. `google`.
+    pub id: String,
+    /// e.g. `https://accounts.google.com`, the discovery document is fetched from
+    /// `<issuer>/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    /// Not needed for public clients, PKCE is used either way
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    /// Space separated, `openid` is always requested
+    #[serde(default = "default_scopes")]
+    pub scopes: String,
+}
+
+fn default_scopes() -> String {
+    "openid email profile".to_string()
+}
+
+impl Config {
+    pub(crate) fn provider(&self, id: &str) -> Result<&ProviderConfig, ft_sdk::Error> {
+        self.oidc_providers
+            .iter()
+            .find(|p| p.id == id)
+            .ok_or_else(|| ft_sdk::single_error("provider", "Unknown login provider.").into())
+    }
+}
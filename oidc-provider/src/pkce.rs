@@ -0,0 +1,28 @@
+//! Proof Key for Code Exchange (RFC 7636). The `verifier` is kept in the session, only its
+//! `challenge` is sent with the authorization request, so an intercepted code is useless without
+//! the session.
+
+/// A random code verifier, 64 characters from the unreserved set allowed by RFC 7636
+pub fn verifier() -> String {
+    ft_sdk::Rng::generate_key(64)
+}
+
+/// The `S256` code challenge for `verifier`
+pub fn challenge(verifier: &str) -> String {
+    use base64::Engine;
+    use sha2::Digest;
+
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(sha2::Sha256::digest(verifier))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_challenge() {
+        // example from RFC 7636, appendix B
+        assert_eq!(
+            super::challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
@@ -25,6 +25,7 @@ fi
 
 # Copy files to destinations
 cp "${SOURCE_DIR}mobile_auth_provider.wasm" "$DEST"
+cp "${SOURCE_DIR}oidc_provider.wasm" "$DEST"
 cp "${SOURCE_DIR}backend.wasm" "$DEST"
 
 echo "WASM files copied successfully to ${DEST}"
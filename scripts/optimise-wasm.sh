@@ -136,4 +136,5 @@ function wasm_opt() {
 ensure_wasm_opt
 
 wasm_opt lets-auth.fifthtry.site/mobile_auth_provider.wasm
+wasm_opt lets-auth.fifthtry.site/oidc_provider.wasm
 wasm_opt lets-auth.fifthtry.site/backend.wasm
@@ -0,0 +1,40 @@
+/// Create a folder under `parents`, a folder with no parents is a root folder. A parent listed
+/// more than once is stored once. A new folder has no children yet, so it can not close a cycle.
+pub fn create_folder(
+    conn: &mut ft_sdk::Connection,
+    name: &str,
+    kind: Option<&str>,
+    parents: &[lets_auth::FolderID],
+) -> Result<lets_auth::Folder, lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_folder;
+
+    let now = ft_sdk::env::now();
+    let folder = lets_auth::Folder {
+        guid: lets_auth::FolderID(ft_sdk::Rng::generate_key(32)),
+        name: name.to_string(),
+        kind: kind.map(ToString::to_string),
+        parents: lets_auth::folder::unique(parents),
+        created_at: now,
+        updated_at: now,
+    };
+
+    // in one transaction, so a parent can not be deleted between the check and the insert
+    conn.transaction(|conn| {
+        lets_auth::folder::check_exists(conn, &folder.parents)?;
+
+        diesel::insert_into(fastn_folder::table)
+            .values((
+                fastn_folder::guid.eq(&folder.guid.0),
+                fastn_folder::name.eq(&folder.name),
+                fastn_folder::kind.eq(&folder.kind),
+                fastn_folder::parents.eq(serde_json::to_string(&folder.parents)?),
+                fastn_folder::created_at.eq(now),
+                fastn_folder::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        lets_auth::folder::set_parents(conn, &folder.guid, &folder.parents)?;
+
+        Ok(folder)
+    })
+}
@@ -0,0 +1,117 @@
+//! The folder graph must stay acyclic, every function that gives a folder a parent checks the new
+//! edges with `check` first.
+
+/// Error if making `parents` the parents of `fid` would make `fid` its own ancestor
+pub(crate) fn check(
+    conn: &mut ft_sdk::Connection,
+    fid: &lets_auth::FolderID,
+    parents: &[lets_auth::FolderID],
+) -> Result<(), lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_folder;
+
+    let path = find_cycle(fid, parents, |folder| {
+        let parents: Option<String> = fastn_folder::table
+            .filter(fastn_folder::guid.eq(&folder.0))
+            .select(fastn_folder::parents)
+            .first(conn)
+            .optional()?;
+
+        match parents {
+            Some(parents) => Ok(serde_json::from_str(&parents)?),
+            None => Ok(vec![]),
+        }
+    })?;
+
+    match path {
+        Some(path) => Err(lets_auth::FolderError::Cycle { path }),
+        None => Ok(()),
+    }
+}
+
+/// The cycle `fid -> parent -> ... -> fid` that giving `fid` the parents `parents` would create,
+/// if any. `parents_of` returns the current parents of a folder.
+fn find_cycle<E>(
+    fid: &lets_auth::FolderID,
+    parents: &[lets_auth::FolderID],
+    mut parents_of: impl FnMut(&lets_auth::FolderID) -> Result<Vec<lets_auth::FolderID>, E>,
+) -> Result<Option<Vec<lets_auth::FolderID>>, E> {
+    // folder -> the folder we reached it from, to rebuild the path once `fid` is found
+    let mut came_from: std::collections::HashMap<lets_auth::FolderID, lets_auth::FolderID> =
+        std::collections::HashMap::new();
+    let mut stack = vec![];
+
+    for parent in parents {
+        if !came_from.contains_key(parent) {
+            came_from.insert(parent.clone(), fid.clone());
+            stack.push(parent.clone());
+        }
+    }
+
+    while let Some(folder) = stack.pop() {
+        if &folder == fid {
+            let mut path = vec![folder];
+            let mut current = fid.clone();
+            loop {
+                current = came_from[&current].clone();
+                path.push(current.clone());
+                if &current == fid {
+                    break;
+                }
+            }
+            path.reverse();
+            return Ok(Some(path));
+        }
+
+        for parent in parents_of(&folder)? {
+            if !came_from.contains_key(&parent) {
+                came_from.insert(parent.clone(), folder.clone());
+                stack.push(parent);
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    fn f(id: &str) -> lets_auth::FolderID {
+        lets_auth::FolderID(id.to_string())
+    }
+
+    fn find_cycle(
+        edges: &[(&str, &str)],
+        fid: &str,
+        parents: &[&str],
+    ) -> Option<Vec<lets_auth::FolderID>> {
+        let parents: Vec<_> = parents.iter().map(|p| f(p)).collect();
+
+        super::find_cycle::<()>(&f(fid), &parents, |folder| {
+            Ok(edges
+                .iter()
+                .filter(|(child, _)| *child == folder.0)
+                .map(|(_, parent)| f(parent))
+                .collect())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_find_cycle() {
+        // root <- a <- b <- c, and root <- d
+        let edges = [("a", "root"), ("b", "a"), ("c", "b"), ("d", "root")];
+
+        assert_eq!(find_cycle(&edges, "c", &["d"]), None);
+        assert_eq!(find_cycle(&edges, "b", &["a", "d"]), None);
+        assert_eq!(find_cycle(&edges, "a", &["a"]), Some(vec![f("a"), f("a")]));
+        assert_eq!(
+            find_cycle(&edges, "a", &["d", "c"]),
+            Some(vec![f("a"), f("c"), f("b"), f("a")])
+        );
+        assert_eq!(
+            find_cycle(&edges, "root", &["d"]),
+            Some(vec![f("root"), f("d"), f("root")])
+        );
+    }
+}
@@ -0,0 +1,56 @@
+/// Delete `fid`. It is removed from the parents of its children, a child that had no other parent
+/// becomes a root folder. Users in `fid` are removed from it, and the `denormalized_folders` of
+/// users below it are updated. Objects in `fid` are taken out of it, and the permissions granted
+/// on it are deleted, the objects themselves belong to their apps and are left alone.
+pub fn delete_folder(
+    conn: &mut ft_sdk::Connection,
+    fid: &lets_auth::FolderID,
+) -> Result<(), lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::{
+        fastn_folder, fastn_folder_object, fastn_folder_permission, fastn_folder_relation,
+    };
+
+    conn.transaction(|conn| {
+        let now = ft_sdk::env::now();
+
+        for child in lets_auth::folder::children(conn, fid)? {
+            let child = child.into_folder()?;
+            let parents: Vec<_> = child.parents.into_iter().filter(|p| p != fid).collect();
+
+            diesel::update(fastn_folder::table.filter(fastn_folder::guid.eq(&child.guid.0)))
+                .set((
+                    fastn_folder::parents.eq(serde_json::to_string(&parents)?),
+                    fastn_folder::updated_at.eq(now),
+                ))
+                .execute(conn)?;
+        }
+
+        diesel::delete(
+            fastn_folder_relation::table.filter(
+                fastn_folder_relation::folder
+                    .eq(&fid.0)
+                    .or(fastn_folder_relation::parent.eq(&fid.0)),
+            ),
+        )
+        .execute(conn)?;
+
+        let deleted = diesel::delete(fastn_folder::table.filter(fastn_folder::guid.eq(&fid.0)))
+            .execute(conn)?;
+
+        if deleted == 0 {
+            return Err(lets_auth::FolderError::NotFound(fid.clone()));
+        }
+
+        diesel::delete(fastn_folder_object::table.filter(fastn_folder_object::fid.eq(&fid.0)))
+            .execute(conn)?;
+        diesel::delete(
+            fastn_folder_permission::table.filter(fastn_folder_permission::fid.eq(&fid.0)),
+        )
+        .execute(conn)?;
+
+        lets_auth::user_folders::resync_users_under(conn, fid)?;
+
+        Ok(())
+    })
+}
@@ -2,25 +2,48 @@ pub fn denormalized_folders(
     conn: &mut ft_sdk::Connection,
     folders: Vec<lets_auth::FolderID>,
 ) -> ft_sdk::Result<std::collections::HashSet<lets_auth::FolderID>> {
+    Ok(ancestors(conn, folders)?)
+}
+
+/// `folders` along with all their ancestors
+pub(crate) fn ancestors(
+    conn: &mut ft_sdk::Connection,
+    folders: Vec<lets_auth::FolderID>,
+) -> Result<std::collections::HashSet<lets_auth::FolderID>, lets_auth::FolderError> {
     use diesel::prelude::*;
     use lets_auth::schema::fastn_folder;
 
+    ancestors_by(folders, |level| {
+        let results: Vec<(String, String)> = fastn_folder::table
+            .filter(fastn_folder::guid.eq_any(level))
+            .select((fastn_folder::guid, fastn_folder::parents))
+            .load(conn)?;
+
+        results
+            .into_iter()
+            .map(|(guid, parents)| Ok((guid, serde_json::from_str(&parents)?)))
+            .collect()
+    })
+}
+
+/// `folders` along with all their ancestors. `parents_of` returns the guid and the parents of
+/// those of the given folders that exist, a level of the folder tree at a time.
+pub(crate) fn ancestors_by<E>(
+    folders: Vec<lets_auth::FolderID>,
+    mut parents_of: impl FnMut(&[String]) -> Result<Vec<(String, Vec<String>)>, E>,
+) -> Result<std::collections::HashSet<lets_auth::FolderID>, E> {
     let mut all_folders = std::collections::HashSet::new();
     let mut stack: Vec<String> = folders.into_iter().map(|f| f.0).collect();
 
     while !stack.is_empty() {
-        let results: Vec<(String, String)> = fastn_folder::table
-            .filter(fastn_folder::guid.eq_any(&stack))
-            .select((fastn_folder::guid, fastn_folder::parents))
-            .load(conn)?;
+        let results = parents_of(&stack)?;
 
         stack.clear();
 
         for (guid, parents) in results {
             let folder_id = lets_auth::FolderID(guid);
             if all_folders.insert(folder_id) {
-                let parent_ids: Vec<String> = serde_json::from_str(&parents)?;
-                stack.extend(parent_ids);
+                stack.extend(parents);
             }
         }
     }
@@ -0,0 +1,25 @@
+#[derive(thiserror::Error, Debug)]
+pub enum FolderError {
+    #[error("folder not found: {}", .0.0)]
+    NotFound(lets_auth::FolderID),
+    #[error("parent folder not found: {}", .0.0)]
+    ParentNotFound(lets_auth::FolderID),
+    /// `path` starts and ends with the folder being given a new parent, and is the cycle the new
+    /// parent would close
+    #[error("folder would become its own ancestor: {}", display_path(.path))]
+    Cycle { path: Vec<lets_auth::FolderID> },
+    /// a page of `list_objects` has to have room for at least one object
+    #[error("limit must be positive: {0}")]
+    InvalidLimit(i64),
+    #[error("database error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("invalid folder parents: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+fn display_path(path: &[lets_auth::FolderID]) -> String {
+    path.iter()
+        .map(|f| f.0.as_str())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
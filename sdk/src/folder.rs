@@ -12,7 +12,7 @@ pub struct Folder {
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(diesel::Queryable, diesel::Selectable)]
+#[derive(diesel::Queryable, diesel::Selectable, diesel::QueryableByName)]
 #[diesel(table_name = lets_auth::schema::fastn_folder)]
 #[diesel(check_for_backend(ft_sdk::Sqlite))]
 pub(crate) struct DbFolder {
@@ -26,8 +26,7 @@ pub(crate) struct DbFolder {
 }
 
 impl DbFolder {
-    #[expect(unused)]
-    pub(crate) fn into_folder(self) -> ft_sdk::Result<Folder> {
+    pub(crate) fn into_folder(self) -> Result<Folder, lets_auth::FolderError> {
         Ok(Folder {
             guid: FolderID(self.guid),
             name: self.name,
@@ -38,3 +37,142 @@ impl DbFolder {
         })
     }
 }
+
+/// Error unless every folder in `folders` exists
+pub(crate) fn check_exists(
+    conn: &mut ft_sdk::Connection,
+    folders: &[FolderID],
+) -> Result<(), lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_folder;
+
+    let guids: Vec<&str> = folders.iter().map(|f| f.0.as_str()).collect();
+
+    let found: std::collections::HashSet<String> = fastn_folder::table
+        .filter(fastn_folder::guid.eq_any(&guids))
+        .select(fastn_folder::guid)
+        .load::<String>(conn)?
+        .into_iter()
+        .collect();
+
+    match first_missing(folders, &found) {
+        Some(missing) => Err(lets_auth::FolderError::ParentNotFound(missing.clone())),
+        None => Ok(()),
+    }
+}
+
+/// The first of `folders` that is not in `found`
+fn first_missing<'a>(
+    folders: &'a [FolderID],
+    found: &std::collections::HashSet<String>,
+) -> Option<&'a FolderID> {
+    folders.iter().find(|f| !found.contains(&f.0))
+}
+
+/// `parents` without repeats, in the order they first appear
+pub(crate) fn unique(parents: &[FolderID]) -> Vec<FolderID> {
+    let mut seen = std::collections::HashSet::new();
+    parents
+        .iter()
+        .filter(|p| seen.insert(*p))
+        .cloned()
+        .collect()
+}
+
+/// Make `parents` the parents of `fid` in `fastn_folder_relation`, to be called whenever
+/// `fastn_folder.parents` of `fid` is written
+pub(crate) fn set_parents(
+    conn: &mut ft_sdk::Connection,
+    fid: &FolderID,
+    parents: &[FolderID],
+) -> Result<(), lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_folder_relation;
+
+    diesel::delete(fastn_folder_relation::table.filter(fastn_folder_relation::folder.eq(&fid.0)))
+        .execute(conn)?;
+
+    for parent in parents {
+        diesel::insert_into(fastn_folder_relation::table)
+            .values((
+                fastn_folder_relation::folder.eq(&fid.0),
+                fastn_folder_relation::parent.eq(&parent.0),
+            ))
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Folders that have `fid` as one of their parents
+pub(crate) fn children(
+    conn: &mut ft_sdk::Connection,
+    fid: &FolderID,
+) -> Result<Vec<DbFolder>, lets_auth::FolderError> {
+    use diesel::prelude::*;
+
+    Ok(diesel::sql_query(
+        r#"
+        SELECT guid, name, kind, parents, created_at, updated_at
+        FROM fastn_folder
+        WHERE guid IN (SELECT folder FROM fastn_folder_relation WHERE parent = $1)
+        ORDER BY name
+        "#,
+    )
+    .bind::<diesel::sql_types::Text, _>(&fid.0)
+    .load(conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+    fn f(id: &str) -> super::FolderID {
+        super::FolderID(id.to_string())
+    }
+
+    fn db_folder(parents: &str) -> super::DbFolder {
+        let at = chrono::DateTime::from_timestamp(1000, 0).unwrap();
+
+        super::DbFolder {
+            guid: "a".to_string(),
+            name: "A".to_string(),
+            kind: None,
+            parents: parents.to_string(),
+            created_at: at,
+            updated_at: at,
+        }
+    }
+
+    #[test]
+    fn test_into_folder() {
+        let folder = db_folder(r#"["root","b"]"#).into_folder().unwrap();
+        assert_eq!(folder.guid, f("a"));
+        assert_eq!(folder.parents, vec![f("root"), f("b")]);
+
+        assert_eq!(db_folder("[]").into_folder().unwrap().parents, vec![]);
+        assert!(matches!(
+            db_folder("root").into_folder(),
+            Err(lets_auth::FolderError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_unique() {
+        assert_eq!(
+            super::unique(&[f("a"), f("b"), f("a"), f("c"), f("b")]),
+            vec![f("a"), f("b"), f("c")]
+        );
+        assert_eq!(super::unique(&[]), vec![]);
+    }
+
+    #[test]
+    fn test_first_missing() {
+        let found = ["a".to_string(), "b".to_string()].into_iter().collect();
+
+        assert_eq!(super::first_missing(&[f("a"), f("b")], &found), None);
+        assert_eq!(
+            super::first_missing(&[f("a"), f("x"), f("y")], &found),
+            Some(&f("x"))
+        );
+        assert_eq!(super::first_missing(&[], &found), None);
+    }
+}
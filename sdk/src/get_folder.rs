@@ -0,0 +1,15 @@
+pub fn get_folder(
+    conn: &mut ft_sdk::Connection,
+    fid: &lets_auth::FolderID,
+) -> Result<Option<lets_auth::Folder>, lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_folder;
+
+    fastn_folder::table
+        .filter(fastn_folder::guid.eq(&fid.0))
+        .select(lets_auth::DbFolder::as_select())
+        .first(conn)
+        .optional()?
+        .map(lets_auth::DbFolder::into_folder)
+        .transpose()
+}
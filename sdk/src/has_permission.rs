@@ -0,0 +1,404 @@
+/// Does user `uid` have `permission` on object `oid` of kind `okind` of `app`. This is the
+/// algorithm described with the `add-folders-and-permissions` migration:
+///
+/// - a grant in `fastn_user_object_permission` for the user and the object is enough,
+/// - else the folders of the object are walked up to the roots, a level at a time, and a
+///   `fastn_folder_permission` on a folder the user is added to is enough.
+///
+/// Any permission that implies `permission` through `parent_permission` counts as well. Grants are
+/// only considered between their `valid_since` and `valid_till`. `two_factor` is not checked here,
+/// asking for a second factor is up to the caller.
+///
+/// A `permission` that `app` has not defined is never granted.
+pub fn has_permission(
+    conn: &mut ft_sdk::Connection,
+    uid: i64,
+    app: &str,
+    okind: &str,
+    oid: i64,
+    permission: &str,
+) -> Result<bool, lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::{
+        fastn_folder, fastn_folder_object, fastn_folder_permission, fastn_user,
+        fastn_user_object_permission,
+    };
+
+    let implying = implying_permissions(conn, app, okind, permission)?;
+    if implying.is_empty() {
+        return Ok(false);
+    }
+
+    let now = ft_sdk::env::now();
+
+    let direct: Vec<Grant> = fastn_user_object_permission::table
+        .filter(fastn_user_object_permission::uid.eq(uid))
+        .filter(fastn_user_object_permission::oid.eq(oid))
+        .filter(fastn_user_object_permission::permission.eq_any(&implying))
+        .select((
+            fastn_user_object_permission::permission,
+            fastn_user_object_permission::valid_since,
+            fastn_user_object_permission::valid_till,
+        ))
+        .load(conn)?;
+
+    if direct.iter().any(|grant| grant.grants(&implying, now)) {
+        return Ok(true);
+    }
+
+    let user_folders: Option<String> = fastn_user::table
+        .filter(fastn_user::id.eq(uid))
+        .select(fastn_user::folders)
+        .first(conn)
+        .optional()?;
+
+    let user_folders: std::collections::HashSet<String> = match user_folders {
+        Some(folders) => serde_json::from_str(&folders)?,
+        None => return Ok(false),
+    };
+
+    if user_folders.is_empty() {
+        return Ok(false);
+    }
+
+    let object_folders: Vec<String> = fastn_folder_object::table
+        .filter(fastn_folder_object::app.eq(app))
+        .filter(fastn_folder_object::okind.eq(okind))
+        .filter(fastn_folder_object::oid.eq(oid))
+        .select(fastn_folder_object::fid)
+        .load(conn)?;
+
+    granted_through_folders(
+        conn,
+        &user_folders,
+        object_folders,
+        |conn, member_of| {
+            let grants: Vec<Grant> = fastn_folder_permission::table
+                .filter(fastn_folder_permission::fid.eq_any(member_of))
+                .filter(fastn_folder_permission::permission.eq_any(&implying))
+                .select((
+                    fastn_folder_permission::permission,
+                    fastn_folder_permission::valid_since,
+                    fastn_folder_permission::valid_till,
+                ))
+                .load(conn)?;
+
+            Ok(grants.iter().any(|grant| grant.grants(&implying, now)))
+        },
+        |conn, level| {
+            let parents: Vec<String> = fastn_folder::table
+                .filter(fastn_folder::guid.eq_any(level))
+                .select(fastn_folder::parents)
+                .load(conn)?;
+
+            let mut all = vec![];
+            for parents in parents {
+                all.extend(serde_json::from_str::<Vec<String>>(&parents)?);
+            }
+
+            Ok(all)
+        },
+    )
+}
+
+/// A permission granted to a user on an object, or to the users of a folder
+#[derive(diesel::Queryable)]
+pub(crate) struct Grant {
+    permission: i64,
+    valid_since: chrono::DateTime<chrono::Utc>,
+    valid_till: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Grant {
+    /// true if this is one of `implying` and is valid at `now`
+    pub(crate) fn grants(&self, implying: &[i64], now: chrono::DateTime<chrono::Utc>) -> bool {
+        implying.contains(&self.permission)
+            && self.valid_since <= now
+            && self.valid_till.is_none_or(|till| till > now)
+    }
+}
+
+/// Walk up from `object_folders`, the folders an object is in, a level at a time, and return true
+/// as soon as `granted` is true for the folders of a level that the user is added to.
+/// `parents_of` returns the parents of the folders of a level. Both are passed `conn`.
+fn granted_through_folders<C, E>(
+    conn: &mut C,
+    user_folders: &std::collections::HashSet<String>,
+    mut level: Vec<String>,
+    mut granted: impl FnMut(&mut C, &[&String]) -> Result<bool, E>,
+    mut parents_of: impl FnMut(&mut C, &[String]) -> Result<Vec<String>, E>,
+) -> Result<bool, E> {
+    // folders can have more than one parent, so the same folder can be reached more than once
+    let mut seen = std::collections::HashSet::new();
+
+    while !level.is_empty() {
+        level.retain(|fid| seen.insert(fid.clone()));
+
+        let member_of: Vec<&String> = level
+            .iter()
+            .filter(|fid| user_folders.contains(*fid))
+            .collect();
+
+        if !member_of.is_empty() && granted(conn, &member_of)? {
+            return Ok(true);
+        }
+
+        if level.is_empty() {
+            break;
+        }
+
+        level = parents_of(conn, &level)?;
+    }
+
+    Ok(false)
+}
+
+/// Ids of `permission` and of every permission above it, following `parent_permission`. Empty if
+/// `app` does not define `permission` for `okind`.
+pub(crate) fn implying_permissions(
+    conn: &mut ft_sdk::Connection,
+    app: &str,
+    okind: &str,
+    permission: &str,
+) -> Result<Vec<i64>, lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_app_permission;
+
+    let Some((id, mut parent)) = fastn_app_permission::table
+        .filter(fastn_app_permission::app.eq(app))
+        .filter(fastn_app_permission::okind.eq(okind))
+        .filter(fastn_app_permission::permission.eq(permission))
+        .select((
+            fastn_app_permission::id,
+            fastn_app_permission::parent_permission,
+        ))
+        .first::<(i64, Option<i64>)>(conn)
+        .optional()?
+    else {
+        return Ok(vec![]);
+    };
+
+    chain(id, parent, |id| {
+        Ok(fastn_app_permission::table
+            .filter(fastn_app_permission::id.eq(id))
+            .select(fastn_app_permission::parent_permission)
+            .first::<Option<i64>>(conn)
+            .optional()?
+            .flatten())
+    })
+}
+
+/// `id` followed by its `parent` and every permission above it. `parent_of` returns the
+/// `parent_permission` of a permission.
+fn chain<E>(
+    id: i64,
+    mut parent: Option<i64>,
+    mut parent_of: impl FnMut(i64) -> Result<Option<i64>, E>,
+) -> Result<Vec<i64>, E> {
+    let mut implying = vec![id];
+
+    // `!implying.contains` guards against a misconfigured loop of parents
+    while let Some(id) = parent.filter(|id| !implying.contains(id)) {
+        implying.push(id);
+        parent = parent_of(id)?;
+    }
+
+    Ok(implying)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    // permission ids, a permission implies those below it
+    const ADMIN: i64 = 1;
+    const WRITE: i64 = 2;
+    const READ: i64 = 3;
+
+    fn at(day: u32) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.with_ymd_and_hms(2025, 1, day, 0, 0, 0).unwrap()
+    }
+
+    fn grant(permission: i64) -> super::Grant {
+        super::Grant {
+            permission,
+            valid_since: at(1),
+            valid_till: None,
+        }
+    }
+
+    /// The folder tree of the `add-folders-and-permissions` migration notes, folder -> parents.
+    /// {8} is listed both under /fifthtry/blog/ and under the root, and /fifthtry/marketing/
+    /// links to it, so it has three parents.
+    ///
+    /// ```txt
+    /// /{1} (X) [admin:*]
+    ///   /fifthtry{2} (SAN) [Blog:Read]
+    ///      /finance{3} ()
+    ///      /marketing{4} (AN) [Blog:Write]
+    ///         ->{8}
+    ///      /tech{5} (SA) [Blog:Write]
+    ///         ->{7}
+    ///      /blog{6} ()
+    ///          /tech-posts{7} ()
+    ///          /marketing{8} ()
+    ///   /today-has-been{8} (AR)
+    /// ```
+    fn parents(fid: &str) -> Vec<String> {
+        let parents: &[&str] = match fid {
+            "2" => &["1"],
+            "3" | "4" | "5" | "6" => &["2"],
+            "7" => &["6", "5"],
+            "8" => &["6", "4", "1"],
+            _ => &[],
+        };
+        parents.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn user_folders(user: char) -> std::collections::HashSet<String> {
+        let folders: &[&str] = match user {
+            'X' => &["1"],
+            'S' => &["2", "5"],
+            'A' => &["2", "4", "5", "8"],
+            'N' => &["2", "4"],
+            'R' => &["8"],
+            _ => &[],
+        };
+        folders.iter().map(|f| f.to_string()).collect()
+    }
+
+    fn folder_grants(fid: &str) -> Vec<super::Grant> {
+        match fid {
+            "1" => vec![grant(ADMIN)],
+            "2" => vec![grant(READ)],
+            "4" | "5" => vec![grant(WRITE)],
+            _ => vec![],
+        }
+    }
+
+    /// `has_permission` for an object in `object_folders`, with `grants_of` instead of
+    /// `fastn_folder_permission`
+    fn check_with(
+        user: char,
+        permission: i64,
+        object_folders: &[&str],
+        grants_of: impl Fn(&str) -> Vec<super::Grant>,
+    ) -> bool {
+        let implying = super::chain(permission, parent_of(permission), |id| {
+            Ok::<_, ()>(parent_of(id))
+        })
+        .unwrap();
+
+        super::granted_through_folders(
+            &mut (),
+            &user_folders(user),
+            object_folders.iter().map(|f| f.to_string()).collect(),
+            |_, member_of| {
+                Ok::<_, ()>(member_of.iter().any(|fid| {
+                    grants_of(fid)
+                        .iter()
+                        .any(|grant| grant.grants(&implying, at(15)))
+                }))
+            },
+            |_, level| Ok(level.iter().flat_map(|fid| parents(fid)).collect()),
+        )
+        .unwrap()
+    }
+
+    fn check(user: char, permission: i64, object_folders: &[&str]) -> bool {
+        check_with(user, permission, object_folders, folder_grants)
+    }
+
+    fn parent_of(id: i64) -> Option<i64> {
+        match id {
+            READ => Some(WRITE),
+            WRITE => Some(ADMIN),
+            _ => None,
+        }
+    }
+
+    fn who(permission: i64, object_folders: &[&str]) -> String {
+        "XSANR"
+            .chars()
+            .filter(|user| check(*user, permission, object_folders))
+            .collect()
+    }
+
+    #[test]
+    fn test_folder_tree() {
+        // a blog under /fifthtry/ can be read by SAN
+        assert_eq!(who(READ, &["7"]), "XSAN");
+        assert_eq!(who(READ, &["3"]), "XSAN");
+        assert_eq!(who(READ, &["8"]), "XSAN");
+        // write comes from /fifthtry/tech/ for {7} and /fifthtry/marketing/ for {8}
+        assert_eq!(who(WRITE, &["7"]), "XSA");
+        assert_eq!(who(WRITE, &["8"]), "XAN");
+        assert_eq!(who(WRITE, &["7", "8"]), "XSAN");
+        // no one but the superuser is part of finance
+        assert_eq!(who(WRITE, &["3"]), "X");
+        // RG is in /today-has-been/ which grants nothing, and access does not flow upwards
+        assert_eq!(who(READ, &["1"]), "X");
+        assert_eq!(who(ADMIN, &["7"]), "X");
+        // an object in no folder needs a direct grant
+        assert_eq!(who(READ, &[]), "");
+    }
+
+    #[test]
+    fn test_parent_permission_chain() {
+        let chain = |id| super::chain(id, parent_of(id), |id| Ok::<_, ()>(parent_of(id)));
+
+        assert_eq!(chain(READ), Ok(vec![READ, WRITE, ADMIN]));
+        assert_eq!(chain(WRITE), Ok(vec![WRITE, ADMIN]));
+        assert_eq!(chain(ADMIN), Ok(vec![ADMIN]));
+
+        // a misconfigured loop of parents ends once it comes back around
+        let looped = |id| match id {
+            1 => Some(2),
+            2 => Some(3),
+            _ => Some(1),
+        };
+        assert_eq!(
+            super::chain(1, looped(1), |id| Ok::<_, ()>(looped(id))),
+            Ok(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn test_grant_validity() {
+        let implying = [READ, WRITE];
+        let valid = |valid_since, valid_till| {
+            super::Grant {
+                permission: WRITE,
+                valid_since,
+                valid_till,
+            }
+            .grants(&implying, at(15))
+        };
+
+        assert!(valid(at(1), None));
+        assert!(valid(at(15), None));
+        assert!(valid(at(1), Some(at(16))));
+        // not valid yet
+        assert!(!valid(at(16), None));
+        // expired, `valid_till` itself is already outside
+        assert!(!valid(at(1), Some(at(14))));
+        assert!(!valid(at(1), Some(at(15))));
+
+        // a permission that does not imply the one asked for
+        assert!(!grant(ADMIN).grants(&implying, at(15)));
+
+        // with the grant on /fifthtry/tech/ expired, SA fall back to read through /fifthtry/
+        let expired = |fid: &str| match fid {
+            "5" => vec![super::Grant {
+                permission: WRITE,
+                valid_since: at(1),
+                valid_till: Some(at(10)),
+            }],
+            fid => folder_grants(fid),
+        };
+        assert!(!check_with('S', WRITE, &["7"], expired));
+        assert!(!check_with('A', WRITE, &["7"], expired));
+        assert!(check_with('S', READ, &["7"], expired));
+        assert!(check_with('X', WRITE, &["7"], expired));
+    }
+}
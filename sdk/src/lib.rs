@@ -7,16 +7,36 @@ extern crate self as lets_auth;
 
 mod all_folders;
 mod config;
+mod create_folder;
+mod cycle;
+mod delete_folder;
 mod denormalized_folders;
+mod error;
 mod first_folder;
 mod folder;
+mod get_folder;
+mod has_permission;
+mod list_children;
+mod list_objects;
+mod rename_folder;
+mod reparent_folder;
 pub mod schema;
+mod user_folders;
 
 pub const SYSTEM: &str = "lets-auth";
 pub type AppUrl = ft_sdk::RequiredAppUrl<SYSTEM>;
 pub use all_folders::all_folders;
 pub use config::Config;
+pub use create_folder::create_folder;
+pub use delete_folder::delete_folder;
 pub use denormalized_folders::denormalized_folders;
-#[expect(unused)]
+pub use error::FolderError;
 pub(crate) use folder::DbFolder;
 pub use folder::{Folder, FolderID};
+pub use get_folder::get_folder;
+pub use has_permission::has_permission;
+pub use list_children::list_children;
+pub use list_objects::list_objects;
+pub use rename_folder::rename_folder;
+pub use reparent_folder::reparent_folder;
+pub use user_folders::{add_user_to_folder, remove_user_from_folder};
@@ -0,0 +1,10 @@
+/// Folders directly under `fid`, sorted by name
+pub fn list_children(
+    conn: &mut ft_sdk::Connection,
+    fid: &lets_auth::FolderID,
+) -> Result<Vec<lets_auth::Folder>, lets_auth::FolderError> {
+    lets_auth::folder::children(conn, fid)?
+        .into_iter()
+        .map(lets_auth::DbFolder::into_folder)
+        .collect()
+}
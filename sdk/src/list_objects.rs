@@ -0,0 +1,198 @@
+/// `(app, okind, oid)` of the objects of kind `okind` of `app` that user `uid` has `permission`
+/// on, for listing pages. The rules are those of `has_permission`: a direct grant in
+/// `fastn_user_object_permission`, or a grant on a folder the user is added to, which covers every
+/// object in that folder or below it.
+///
+/// Objects are ordered by `oid`. Pass the `oid` of the last object of a page as `after` to get the
+/// next page, `None` for the first one. A page shorter than `limit` is the last one. `limit` has
+/// to be positive, `FolderError::InvalidLimit` otherwise.
+pub fn list_objects(
+    conn: &mut ft_sdk::Connection,
+    uid: i64,
+    app: &str,
+    okind: &str,
+    permission: &str,
+    after: Option<i64>,
+    limit: i64,
+) -> Result<Vec<(String, String, i64)>, lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::{fastn_folder_permission, fastn_folder_relation, fastn_user};
+
+    #[derive(diesel::QueryableByName)]
+    struct Object {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        oid: i64,
+    }
+
+    check_limit(limit)?;
+
+    let implying = lets_auth::has_permission::implying_permissions(conn, app, okind, permission)?;
+    if implying.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let now = ft_sdk::env::now();
+
+    let user_folders: Vec<String> = match fastn_user::table
+        .filter(fastn_user::id.eq(uid))
+        .select(fastn_user::folders)
+        .first::<String>(conn)
+        .optional()?
+    {
+        Some(folders) => serde_json::from_str(&folders)?,
+        None => vec![],
+    };
+
+    // the user's folders that grant the permission, it covers every folder below them too
+    let grants: Vec<(String, lets_auth::has_permission::Grant)> = fastn_folder_permission::table
+        .filter(fastn_folder_permission::fid.eq_any(&user_folders))
+        .filter(fastn_folder_permission::permission.eq_any(&implying))
+        .select((
+            fastn_folder_permission::fid,
+            (
+                fastn_folder_permission::permission,
+                fastn_folder_permission::valid_since,
+                fastn_folder_permission::valid_till,
+            ),
+        ))
+        .load(conn)?;
+
+    let granted_on = grants
+        .into_iter()
+        .filter(|(_, grant)| grant.grants(&implying, now))
+        .map(|(fid, _)| fid)
+        .collect();
+
+    let granted = below(granted_on, |level| {
+        fastn_folder_relation::table
+            .filter(fastn_folder_relation::parent.eq_any(level))
+            .select(fastn_folder_relation::folder)
+            .load::<String>(conn)
+    })?;
+
+    let objects: Vec<Object> = diesel::sql_query(
+        r#"
+        SELECT oid
+        FROM fastn_folder_object
+        WHERE
+            app = $1
+            AND okind = $2
+            AND oid > $3
+            AND fid IN (SELECT value FROM json_each($4))
+        UNION
+        SELECT oid
+        FROM fastn_user_object_permission
+        WHERE
+            uid = $5
+            AND oid > $3
+            AND permission IN (SELECT value FROM json_each($6))
+            AND valid_since <= $7
+            AND (valid_till IS NULL OR valid_till > $7)
+        ORDER BY oid
+        LIMIT $8
+        "#,
+    )
+    .bind::<diesel::sql_types::Text, _>(app)
+    .bind::<diesel::sql_types::Text, _>(okind)
+    .bind::<diesel::sql_types::BigInt, _>(after.unwrap_or(i64::MIN))
+    .bind::<diesel::sql_types::Text, _>(serde_json::to_string(&granted)?)
+    .bind::<diesel::sql_types::BigInt, _>(uid)
+    .bind::<diesel::sql_types::Text, _>(serde_json::to_string(&implying)?)
+    .bind::<diesel::sql_types::Timestamptz, _>(now)
+    .bind::<diesel::sql_types::BigInt, _>(limit)
+    .load(conn)?;
+
+    Ok(objects
+        .into_iter()
+        .map(|o| (app.to_string(), okind.to_string(), o.oid))
+        .collect())
+}
+
+/// A page can not be empty, `LIMIT` would return everything for a negative `limit`
+fn check_limit(limit: i64) -> Result<(), lets_auth::FolderError> {
+    if limit <= 0 {
+        return Err(lets_auth::FolderError::InvalidLimit(limit));
+    }
+
+    Ok(())
+}
+
+/// `folders` and every folder below them. `children_of` returns the children of the folders of a
+/// level of the folder tree.
+fn below<E>(
+    folders: Vec<String>,
+    mut children_of: impl FnMut(&[String]) -> Result<Vec<String>, E>,
+) -> Result<std::collections::HashSet<String>, E> {
+    let mut all = std::collections::HashSet::new();
+    let mut level = folders;
+
+    while !level.is_empty() {
+        // folders can have more than one parent, so the same folder can be reached more than once
+        level.retain(|fid| all.insert(fid.clone()));
+
+        if level.is_empty() {
+            break;
+        }
+
+        level = children_of(&level)?;
+    }
+
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    /// The folder tree of the `add-folders-and-permissions` migration notes, see
+    /// `has_permission::tests::parents`, folder -> children
+    fn children(fid: &str) -> Vec<String> {
+        let children: &[&str] = match fid {
+            "1" => &["2", "8"],
+            "2" => &["3", "4", "5", "6"],
+            "4" => &["8"],
+            "5" => &["7"],
+            "6" => &["7", "8"],
+            _ => &[],
+        };
+        children.iter().map(|c| c.to_string()).collect()
+    }
+
+    fn below(folders: &[&str]) -> Vec<String> {
+        let mut below: Vec<String> =
+            super::below(folders.iter().map(|f| f.to_string()).collect(), |level| {
+                Ok::<_, ()>(level.iter().flat_map(|fid| children(fid)).collect())
+            })
+            .unwrap()
+            .into_iter()
+            .collect();
+        below.sort();
+        below
+    }
+
+    #[test]
+    fn test_below() {
+        // the same folders `has_permission` finds write access on for SA and AN
+        assert_eq!(below(&["5"]), ["5", "7"]);
+        assert_eq!(below(&["4"]), ["4", "8"]);
+        assert_eq!(below(&["4", "5"]), ["4", "5", "7", "8"]);
+        assert_eq!(below(&["2"]), ["2", "3", "4", "5", "6", "7", "8"]);
+        assert_eq!(below(&["1"]), ["1", "2", "3", "4", "5", "6", "7", "8"]);
+        // a folder reached from two parents, and a folder listed twice, are listed once
+        assert_eq!(below(&["6", "4"]), ["4", "6", "7", "8"]);
+        assert_eq!(below(&["8", "8"]), ["8"]);
+        assert_eq!(below(&[]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_check_limit() {
+        assert!(super::check_limit(1).is_ok());
+        assert!(super::check_limit(100).is_ok());
+        assert!(matches!(
+            super::check_limit(0),
+            Err(lets_auth::FolderError::InvalidLimit(0))
+        ));
+        assert!(matches!(
+            super::check_limit(-1),
+            Err(lets_auth::FolderError::InvalidLimit(-1))
+        ));
+    }
+}
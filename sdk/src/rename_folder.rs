@@ -0,0 +1,21 @@
+pub fn rename_folder(
+    conn: &mut ft_sdk::Connection,
+    fid: &lets_auth::FolderID,
+    name: &str,
+) -> Result<(), lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_folder;
+
+    let updated = diesel::update(fastn_folder::table.filter(fastn_folder::guid.eq(&fid.0)))
+        .set((
+            fastn_folder::name.eq(name),
+            fastn_folder::updated_at.eq(ft_sdk::env::now()),
+        ))
+        .execute(conn)?;
+
+    if updated == 0 {
+        return Err(lets_auth::FolderError::NotFound(fid.clone()));
+    }
+
+    Ok(())
+}
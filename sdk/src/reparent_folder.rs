@@ -0,0 +1,33 @@
+/// Replace the parents of `fid` with `parents`, an empty list makes it a root folder. A parent
+/// listed more than once is stored once. Fails with
+/// `FolderError::Cycle` if `fid` would become its own ancestor. The `denormalized_folders` of
+/// users in `fid` or below it are updated in the same transaction.
+pub fn reparent_folder(
+    conn: &mut ft_sdk::Connection,
+    fid: &lets_auth::FolderID,
+    parents: &[lets_auth::FolderID],
+) -> Result<(), lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_folder;
+
+    let parents = lets_auth::folder::unique(parents);
+
+    conn.transaction(|conn| {
+        lets_auth::folder::check_exists(conn, &parents)?;
+        lets_auth::cycle::check(conn, fid, &parents)?;
+
+        let updated = diesel::update(fastn_folder::table.filter(fastn_folder::guid.eq(&fid.0)))
+            .set((
+                fastn_folder::parents.eq(serde_json::to_string(&parents)?),
+                fastn_folder::updated_at.eq(ft_sdk::env::now()),
+            ))
+            .execute(conn)?;
+
+        if updated == 0 {
+            return Err(lets_auth::FolderError::NotFound(fid.clone()));
+        }
+
+        lets_auth::folder::set_parents(conn, fid, &parents)?;
+        lets_auth::user_folders::resync_users_under(conn, fid)
+    })
+}
@@ -14,6 +14,18 @@ diesel::table! {
     }
 }
 
+// the parents of every folder, one row per entry of `fastn_folder.parents`. It is indexed by
+// parent, so the folders below a folder are found without reading the parents of every folder.
+diesel::table! {
+    fastn_folder_relation (id) {
+        id -> Int8,
+        // guid of the folder
+        folder -> Text,
+        // guid of one of its parents
+        parent -> Text,
+    }
+}
+
 diesel::table! {
     fastn_user (id) {
         id -> Int8,
@@ -40,5 +52,71 @@ diesel::table! {
     }
 }
 
+// permissions an app defines for a kind of object, e.g. blog:post:write. A permission implies
+// its children: a permission whose parent_permission is write is granted to anyone with write.
+diesel::table! {
+    fastn_app_permission (id) {
+        id -> Int8,
+        app -> Text,
+        okind -> Text,
+        permission -> Text,
+        parent_permission -> Nullable<Int8>,
+    }
+}
+
+// the folders an object is in
+diesel::table! {
+    fastn_folder_object (id) {
+        id -> Int8,
+        // guid of the folder
+        fid -> Text,
+        app -> Text,
+        okind -> Text,
+        oid -> Int8,
+    }
+}
+
+// users added to fid have permission on every object in fid or below it
+diesel::table! {
+    fastn_folder_permission (id) {
+        id -> Int8,
+        // guid of the folder
+        fid -> Text,
+        permission -> Int8,
+
+        valid_since -> Timestamptz,
+        // if null, the permission is valid forever
+        valid_till -> Nullable<Timestamptz>,
+        two_factor -> Bool,
+    }
+}
+
+// permission granted to a user on an object directly, outside the folder tree
+diesel::table! {
+    fastn_user_object_permission (id) {
+        id -> Int8,
+        uid -> Int8,
+        oid -> Int8,
+        permission -> Int8,
+
+        valid_since -> Timestamptz,
+        // if null, the permission is valid forever
+        valid_till -> Nullable<Timestamptz>,
+        two_factor -> Bool,
+    }
+}
+
 diesel::joinable!(fastn_session -> fastn_user (uid));
-diesel::allow_tables_to_appear_in_same_query!(fastn_user, fastn_session,);
+diesel::joinable!(fastn_folder_permission -> fastn_app_permission (permission));
+diesel::joinable!(fastn_user_object_permission -> fastn_app_permission (permission));
+diesel::joinable!(fastn_user_object_permission -> fastn_user (uid));
+diesel::allow_tables_to_appear_in_same_query!(
+    fastn_user,
+    fastn_session,
+    fastn_folder,
+    fastn_folder_relation,
+    fastn_app_permission,
+    fastn_folder_object,
+    fastn_folder_permission,
+    fastn_user_object_permission,
+);
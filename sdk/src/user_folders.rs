@@ -0,0 +1,242 @@
+//! `fastn_user.folders` are the folders a user is added to, `fastn_user.denormalized_folders` are
+//! those folders along with all their ancestors. The functions here are the only writers of both
+//! columns, and keep the second in sync with the first and with the folder graph.
+
+/// Add user `uid` to folder `fid`, adding them again is a no-op
+pub fn add_user_to_folder(
+    conn: &mut ft_sdk::Connection,
+    uid: i64,
+    fid: &lets_auth::FolderID,
+) -> Result<(), lets_auth::FolderError> {
+    conn.transaction(|conn| {
+        // in the transaction, so `fid` can not be deleted between the check and the save
+        if !exists(conn, fid)? {
+            return Err(lets_auth::FolderError::NotFound(fid.clone()));
+        }
+
+        let mut folders = folders(conn, uid)?;
+
+        if !folders.contains(fid) {
+            folders.push(fid.clone());
+            save(conn, uid, folders)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Remove user `uid` from folder `fid`, they keep access through other folders they are in
+pub fn remove_user_from_folder(
+    conn: &mut ft_sdk::Connection,
+    uid: i64,
+    fid: &lets_auth::FolderID,
+) -> Result<(), lets_auth::FolderError> {
+    conn.transaction(|conn| {
+        let mut folders = folders(conn, uid)?;
+        let count = folders.len();
+
+        folders.retain(|f| f != fid);
+
+        if folders.len() != count {
+            save(conn, uid, folders)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Recompute `denormalized_folders` of every user whose folders are `fid` or below it, to be
+/// called after the ancestors of `fid` change. `fid` itself is removed from the users' folders if
+/// it no longer exists.
+pub(crate) fn resync_users_under(
+    conn: &mut ft_sdk::Connection,
+    fid: &lets_auth::FolderID,
+) -> Result<(), lets_auth::FolderError> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct User {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        id: i64,
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        folders: String,
+    }
+
+    let users: Vec<User> = diesel::sql_query(
+        r#"
+        SELECT id, folders
+        FROM fastn_user
+        WHERE EXISTS (
+            SELECT 1 FROM json_each(fastn_user.denormalized_folders) WHERE value = $1
+        )
+        "#,
+    )
+    .bind::<diesel::sql_types::Text, _>(&fid.0)
+    .load(conn)?;
+
+    let exists = exists(conn, fid)?;
+
+    for user in users {
+        let folders = resynced(serde_json::from_str(&user.folders)?, fid, exists);
+        save(conn, user.id, folders)?;
+    }
+
+    Ok(())
+}
+
+/// The folders of a user in `folders` once `fid` has changed, `fid` is dropped if it no longer
+/// `exists`
+fn resynced(
+    mut folders: Vec<lets_auth::FolderID>,
+    fid: &lets_auth::FolderID,
+    exists: bool,
+) -> Vec<lets_auth::FolderID> {
+    if !exists {
+        folders.retain(|f| f != fid);
+    }
+
+    folders
+}
+
+fn exists(
+    conn: &mut ft_sdk::Connection,
+    fid: &lets_auth::FolderID,
+) -> Result<bool, lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_folder;
+
+    let count: i64 = fastn_folder::table
+        .filter(fastn_folder::guid.eq(&fid.0))
+        .count()
+        .get_result(conn)?;
+
+    Ok(count > 0)
+}
+
+fn folders(
+    conn: &mut ft_sdk::Connection,
+    uid: i64,
+) -> Result<Vec<lets_auth::FolderID>, lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_user;
+
+    let folders: String = fastn_user::table
+        .filter(fastn_user::id.eq(uid))
+        .select(fastn_user::folders)
+        .first(conn)?;
+
+    Ok(serde_json::from_str(&folders)?)
+}
+
+/// Store `folders` for `uid` along with the `denormalized_folders` computed from them
+fn save(
+    conn: &mut ft_sdk::Connection,
+    uid: i64,
+    folders: Vec<lets_auth::FolderID>,
+) -> Result<(), lets_auth::FolderError> {
+    use diesel::prelude::*;
+    use lets_auth::schema::fastn_user;
+
+    let denormalized = sorted(lets_auth::denormalized_folders::ancestors(
+        conn,
+        folders.clone(),
+    )?);
+
+    diesel::update(fastn_user::table.filter(fastn_user::id.eq(uid)))
+        .set((
+            fastn_user::folders.eq(serde_json::to_string(&folders)?),
+            fastn_user::denormalized_folders.eq(serde_json::to_string(&denormalized)?),
+            fastn_user::updated_at.eq(ft_sdk::env::now()),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// `denormalized_folders` as stored, sorted so the column does not change when the set does not
+fn sorted(folders: std::collections::HashSet<lets_auth::FolderID>) -> Vec<lets_auth::FolderID> {
+    let mut folders: Vec<_> = folders.into_iter().collect();
+    folders.sort_by(|a, b| a.0.cmp(&b.0));
+    folders
+}
+
+#[cfg(test)]
+mod tests {
+    type Tree = std::collections::HashMap<&'static str, Vec<&'static str>>;
+
+    fn f(id: &str) -> lets_auth::FolderID {
+        lets_auth::FolderID(id.to_string())
+    }
+
+    fn fs(ids: &[&str]) -> Vec<lets_auth::FolderID> {
+        ids.iter().map(|id| f(id)).collect()
+    }
+
+    /// What `save` stores as `denormalized_folders` for a user in `folders`
+    fn denormalized(tree: &Tree, folders: &[&str]) -> Vec<lets_auth::FolderID> {
+        let ancestors = lets_auth::denormalized_folders::ancestors_by::<()>(fs(folders), |level| {
+            Ok(level
+                .iter()
+                .filter_map(|guid| {
+                    let parents = tree.get(guid.as_str())?;
+                    Some((
+                        guid.clone(),
+                        parents.iter().map(|p| p.to_string()).collect(),
+                    ))
+                })
+                .collect())
+        })
+        .unwrap();
+
+        super::sorted(ancestors)
+    }
+
+    /// What `delete_folder` does to the tree before `resync_users_under` runs
+    fn delete(tree: &mut Tree, fid: &str) {
+        tree.remove(fid);
+        for parents in tree.values_mut() {
+            parents.retain(|p| *p != fid);
+        }
+    }
+
+    #[test]
+    fn test_denormalized_folders() {
+        // root <- a <- c, root <- b <- d
+        let mut tree: Tree = [
+            ("root", vec![]),
+            ("a", vec!["root"]),
+            ("b", vec!["root"]),
+            ("c", vec!["a"]),
+            ("d", vec!["b"]),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(denormalized(&tree, &["c"]), fs(&["a", "c", "root"]));
+        assert_eq!(
+            denormalized(&tree, &["c", "d"]),
+            fs(&["a", "b", "c", "d", "root"])
+        );
+        assert_eq!(denormalized(&tree, &[]), fs(&[]));
+
+        // reparent c under a and d
+        tree.insert("c", vec!["a", "d"]);
+        assert_eq!(
+            denormalized(&tree, &["c"]),
+            fs(&["a", "b", "c", "d", "root"])
+        );
+
+        // delete b, d becomes a root folder and c keeps a
+        delete(&mut tree, "b");
+        assert_eq!(denormalized(&tree, &["c"]), fs(&["a", "c", "d", "root"]));
+        assert_eq!(denormalized(&tree, &["d"]), fs(&["d"]));
+
+        // a user in the deleted folder loses it, a user below it keeps their own folder
+        let folders = super::resynced(fs(&["b", "a"]), &f("b"), false);
+        assert_eq!(folders, fs(&["a"]));
+        assert_eq!(denormalized(&tree, &["a"]), fs(&["a", "root"]));
+        assert_eq!(super::resynced(fs(&["d"]), &f("b"), false), fs(&["d"]));
+        // after a reparent the folder still exists and is kept
+        assert_eq!(super::resynced(fs(&["c"]), &f("c"), true), fs(&["c"]));
+    }
+}